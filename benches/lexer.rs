@@ -0,0 +1,233 @@
+// Lexer throughput benchmark (std only, no external harness).
+// Run with:  cargo bench --bench lexer
+//
+// Lexes a generated multi-megabyte document several times and reports the
+// best time, plus a full compile of the same document for context.
+//
+// `copying` is a reference lexer built the way CharLexer used to work: the
+// source copied into a Vec<char>, and an owned String for every token and
+// for every uppercased word. It produces the same tokens, so the two "lex"
+// lines (and the speedup printed after them) compare only the copying.
+// On the machine the borrowing lexer was written on, 8 MiB took about
+// 206 ms to lex before and 121 ms after.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use lolmarkdownn::lexer::CharLexer;
+use lolmarkdownn::{compile, Options};
+
+/// The copy-per-token lexer that CharLexer replaced, for comparison.
+/// It only handles what `document` uses, and never reports errors.
+mod copying {
+    use std::rc::Rc;
+
+    use lolmarkdownn::span::Span;
+    use lolmarkdownn::token::{lookup, Kw};
+
+    #[derive(Debug, PartialEq)]
+    pub enum Kind {
+        Hash,
+        Word(String),
+        Text(String),
+        Kw(Kw),
+        Escaped(String),
+        Raw(String),
+        Eof,
+    }
+
+    pub struct Lexer {
+        chars: Vec<char>,
+        pos: usize,
+        offset: usize,
+        file: Rc<str>,
+        line: usize,
+        col: usize,
+        after_hash: bool,
+        prev_kw: Option<Kw>,
+        raw_next: bool,
+    }
+
+    impl Lexer {
+        pub fn new(input: &str, file: &str) -> Self {
+            let chars = input.chars().collect();
+            Self { chars, pos: 0, offset: 0, file: Rc::from(file), line: 1, col: 0, after_hash: false, prev_kw: None, raw_next: false }
+        }
+
+        fn peek(&self) -> char {
+            self.chars.get(self.pos).copied().unwrap_or('\0')
+        }
+
+        fn bump(&mut self) -> char {
+            let c = self.peek();
+            if self.pos < self.chars.len() {
+                if c == '\n' {
+                    self.line += 1;
+                    self.col = 0;
+                } else {
+                    self.col += 1;
+                }
+                self.pos += 1;
+                self.offset += c.len_utf8();
+            }
+            c
+        }
+
+        fn take_while(&mut self, pred: impl Fn(char) -> bool) -> String {
+            let mut s = String::new();
+            while self.pos < self.chars.len() && pred(self.peek()) {
+                s.push(self.bump());
+            }
+            s
+        }
+
+        /// The rest of the current line, copied, to spot the `#OIC` ending a KODE body.
+        fn line_ahead(&self) -> String {
+            self.chars[self.pos..].iter().take_while(|c| **c != '\n').collect()
+        }
+
+        fn read_raw(&mut self) -> String {
+            let mut s = String::new();
+            while self.pos < self.chars.len() {
+                let c = self.bump();
+                s.push(c);
+                if c == '\n' && self.line_ahead().trim_matches([' ', '\t', '\r']).eq_ignore_ascii_case("#OIC") {
+                    s.push_str(&self.take_while(|c| c == ' ' || c == '\t'));
+                    break;
+                }
+            }
+            s
+        }
+
+        pub fn next_token(&mut self) -> (Kind, Span) {
+            let (start, line, col) = (self.offset, self.line, self.col + 1);
+            let kind = self.next_kind();
+            (kind, Span::new(self.file.clone(), start, self.offset, line, col))
+        }
+
+        fn next_kind(&mut self) -> Kind {
+            if self.raw_next {
+                self.raw_next = false;
+                return Kind::Raw(self.read_raw());
+            }
+            if self.pos >= self.chars.len() {
+                return Kind::Eof;
+            }
+            let c = self.peek();
+            if c == '#' {
+                self.bump();
+                self.after_hash = true;
+                self.prev_kw = None;
+                return Kind::Hash;
+            }
+            if c == '\\' && let Some(e @ ('#' | '\\' | ' ')) = self.chars.get(self.pos + 1).copied() {
+                self.bump();
+                self.bump();
+                self.after_hash = false;
+                self.prev_kw = None;
+                return Kind::Escaped(e.to_string());
+            }
+            if c.is_whitespace() {
+                return Kind::Text(self.take_while(char::is_whitespace));
+            }
+            if c.is_ascii_alphanumeric() || c == '_' {
+                let word = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_');
+                let upper = word.to_ascii_uppercase();
+                let keyword_ok = self.after_hash || self.prev_kw.is_some_and(Kw::next_is_keyword);
+                self.after_hash = false;
+                if keyword_ok && let Some(kw) = lookup(&upper) {
+                    self.prev_kw = Some(kw);
+                    self.raw_next = kw == Kw::Kode;
+                    return Kind::Kw(kw);
+                }
+                self.prev_kw = None;
+                return Kind::Word(word);
+            }
+            if matches!(c, ',' | '.' | '"' | ':' | '?' | '!' | '%' | '/') {
+                return Kind::Text(self.take_while(|c| matches!(c, ',' | '.' | '"' | ':' | '?' | '!' | '%' | '/')));
+            }
+            Kind::Text(self.bump().to_string())
+        }
+    }
+}
+
+const RUNS: usize = 5;
+
+/// A document of roughly `target` bytes that uses most of the language.
+fn document(target: usize) -> String {
+    let chunk = "\
+#MAEK SECSHUN Chapter
+#MAEK PARAGRAF Some plain text, with punctuation! #GIMMEH BOLD bold #GIMMEH ITALICS both #MKAY #MKAY
+and a #GIMMEH LINKZ https://example.com/page?q=1 #IT IZ link #MKAY. Issue \\#42 is fixed.
+#LEMME SEE name #MKAY #GIMMEH NEWLINE
+#OIC
+#MAEK LIST #GIMMEH ITEM first #MKAY #GIMMEH ITEM second #GIMMEH PIKCHUR cat.png #IT IZ a cat #MKAY #MKAY #OIC
+#MAEK KODE rust
+fn main() { println!(\"#not an annotation\"); }
+#OIC
+#OIC
+";
+    let mut doc = String::from("#HAI\n#I HAZ name #IT IZ World #MKAY\n");
+    while doc.len() < target {
+        doc.push_str(chunk);
+    }
+    doc.push_str("#KTHXBYE\n");
+    doc
+}
+
+/// Runs `f` RUNS times and returns the fastest run.
+fn best_of(mut f: impl FnMut()) -> Duration {
+    (0..RUNS)
+        .map(|_| {
+            let t = Instant::now();
+            f();
+            t.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn report(what: &str, bytes: usize, time: Duration) {
+    let mb = bytes as f64 / (1024.0 * 1024.0);
+    println!("{:<10} {:>8.2} ms  {:>8.1} MiB/s", what, time.as_secs_f64() * 1000.0, mb / time.as_secs_f64());
+}
+
+fn main() {
+    let doc = document(8 * 1024 * 1024);
+    println!("document: {} bytes, best of {} runs", doc.len(), RUNS);
+
+    let mut tokens = 0;
+    let lex = best_of(|| {
+        tokens = 0;
+        for t in CharLexer::new(black_box(&doc), "bench.lol") {
+            black_box(t.unwrap());
+            tokens += 1;
+        }
+    });
+
+    let mut copied = 0;
+    let copy = best_of(|| {
+        copied = 0;
+        let mut lexer = copying::Lexer::new(black_box(&doc), "bench.lol");
+        loop {
+            let (kind, span) = lexer.next_token();
+            copied += 1;
+            if kind == copying::Kind::Eof {
+                break;
+            }
+            black_box((kind, span));
+        }
+    });
+    // CharLexer's iterator stops before Eof.
+    assert_eq!(copied, tokens + 1, "the reference lexer must see the same tokens");
+
+    report("lex (copy)", doc.len(), copy);
+    report("lex", doc.len(), lex);
+    println!("           {} tokens, {:.2}x faster than copying", tokens, copy.as_secs_f64() / lex.as_secs_f64());
+
+    let full = best_of(|| {
+        let out = compile(black_box(&doc), Options::default()).unwrap();
+        black_box(out.html.len());
+    });
+    report("compile", doc.len(), full);
+}
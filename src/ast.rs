@@ -1,5 +1,21 @@
+use crate::span::Span;
+
+/// One node of the syntax tree, with the source region it was parsed from.
 #[derive(Debug, Clone)]
-pub enum Node {
+pub struct Node {
+    pub kind: NodeKind,
+    pub span: Span,
+}
+
+impl Node {
+    pub fn new(kind: NodeKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[allow(dead_code)] // Html/Body wrappers are not produced by the parser yet
+pub enum NodeKind {
     Html(Vec<Node>),
 
     Comment(String),
@@ -11,18 +27,73 @@ pub enum Node {
 
     Paragraph(Vec<Node>),
 
-    Bold(String),
-    Italics(String),
+    // Visible heading (<h1>..<h6>). `id` is the anchor slug, filled in by the semantic pass.
+    // `level` is the number as written; the semantic pass checks it is 1 to 6.
+    Heading { level: u32, id: String, children: Vec<Node> },
+
+    // Titled section. `level` is the heading level of its title (from how deeply
+    // it is nested); `id` is the anchor slug, filled in by the semantic pass.
+    Section { title: String, level: u8, id: String, children: Vec<Node> },
+
+    // Table of contents; the semantic pass replaces it with a List of links to every section.
+    Toc,
+
+    // Inline formatting; children are text, other formatting, variables and newlines.
+    Bold(Vec<Node>),
+    Italics(Vec<Node>),
+
+    // Hyperlink; children are the label, written like the contents of BOLD.
+    Link { href: String, children: Vec<Node> },
 
     List(Vec<Node>),
+    OrderedList { start: Option<u32>, style: NumberStyle, items: Vec<Node> },
     ListItem(Vec<Node>),
 
+    // Table; rows hold cells, and a header cell is written as <th>.
+    Table(Vec<Node>),
+    TableRow(Vec<Node>),
+    TableCell { header: bool, children: Vec<Node> },
+
+    // Verbatim code; `lang` is empty when no language was given.
+    CodeBlock { lang: String, text: String },
+
     Newline,
     Audio(String),
     Video(String),
+    // Image; an empty `alt` means the author gave no description.
+    Image { src: String, alt: String },
 
     Text(String),
+    // Text from escape sequences (e.g. "\ "); written as-is and never trimmed.
+    Literal(String),
 
     VarDef { name: String, value: String },
     VarUse { name: String },
+
+    // Placeholder for an element the parser could not read.
+    Error,
+}
+
+/// How the items of an ordered list are numbered (HTML's `type` attribute).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NumberStyle {
+    #[default]
+    Decimal,
+    LowerAlpha,
+    UpperAlpha,
+    LowerRoman,
+    UpperRoman,
+}
+
+impl NumberStyle {
+    /// The value of `<ol type="...">`, or None for the default numbering.
+    pub fn html_type(self) -> Option<&'static str> {
+        match self {
+            NumberStyle::Decimal => None,
+            NumberStyle::LowerAlpha => Some("a"),
+            NumberStyle::UpperAlpha => Some("A"),
+            NumberStyle::LowerRoman => Some("i"),
+            NumberStyle::UpperRoman => Some("I"),
+        }
+    }
 }
@@ -0,0 +1,234 @@
+// cli.rs
+// This file reads the command-line arguments.
+// It turns them into a Command for main.rs to run, and defines the
+// exit codes used for each kind of failure.
+
+use std::path::PathBuf;
+
+use lolmarkdownn::{DiagnosticKind, HtmlFormat};
+
+/// Exit codes, one per class of failure.
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_IO: i32 = 3;
+pub const EXIT_LEXICAL: i32 = 4;
+pub const EXIT_SYNTAX: i32 = 5;
+pub const EXIT_SEMANTIC: i32 = 6;
+
+pub const USAGE: &str = "\
+Usage: lolmarkdownn [OPTIONS] <file.lol>
+
+Compiles a LOLCODE markdown file to HTML.
+
+Options:
+  -o, --output <file>  Write the HTML to <file> (default: input with .html)
+      --stdout         Write the HTML to standard output instead of a file
+      --format <fmt>   Output format: legacy (default) or html5
+      --open           Open the generated page in a browser
+      --browser <cmd>  Browser command to use (implies --open; default:
+                       $BROWSER or xdg-open on Linux, the system browser elsewhere)
+      --no-open        Never open a browser (the default)
+      --dump-tokens    Print the tokens, one per line, and stop after lexing
+      --dump-ast[=json]
+                       Print the syntax tree (indented, or as JSON) and stop
+                       after parsing
+      --keywords       List every annotation and exit
+      --grammar        Print the BNF rules of every annotation and exit
+  -h, --help           Print this help and exit
+  -V, --version        Print the version and exit
+
+Exit codes:
+  0 success, 2 usage, 3 I/O, 4 lexical, 5 syntax, 6 semantic error";
+
+/// Settings for one compile run.
+#[derive(Debug)]
+pub struct Options {
+    pub input: PathBuf,
+    pub output: Option<PathBuf>,
+    pub stdout: bool,
+    pub format: HtmlFormat,
+    pub open: bool,
+    pub browser: Option<String>,
+    pub dump: Option<Dump>,
+}
+
+/// A debugging view that replaces the HTML output (printed to stdout).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dump {
+    Tokens,
+    AstTree,
+    AstJson,
+}
+
+/// What the user asked the program to do.
+#[derive(Debug)]
+pub enum Command {
+    Compile(Options),
+    Help,
+    Version,
+    Keywords,
+    Grammar,
+}
+
+/// Parses the arguments that follow the program name.
+/// Returns a usage error message if they do not make sense.
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut args = args.into_iter().peekable();
+    let mut input: Option<PathBuf> = None;
+    let mut output: Option<PathBuf> = None;
+    let mut stdout = false;
+    let mut format = HtmlFormat::default();
+    let mut open = false;
+    let mut no_open = false;
+    let mut browser: Option<String> = None;
+    let mut dump: Option<Dump> = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "--keywords" => return Ok(Command::Keywords),
+            "--grammar" => return Ok(Command::Grammar),
+            "--stdout" => stdout = true,
+            "--dump-tokens" => dump = Some(Dump::Tokens),
+            // The view may also follow as its own argument; no input file is named json or tree.
+            "--dump-ast" => {
+                let json = args.next_if(|a| a == "json" || a == "tree").is_some_and(|a| a == "json");
+                dump = Some(if json { Dump::AstJson } else { Dump::AstTree });
+            }
+            "--dump-ast=tree" => dump = Some(Dump::AstTree),
+            "--dump-ast=json" => dump = Some(Dump::AstJson),
+            "--format" => {
+                let name = args.next().ok_or("--format needs legacy or html5")?;
+                format = parse_format(&name)?;
+            }
+            _ if arg.starts_with("--format=") => format = parse_format(&arg["--format=".len()..])?,
+            "--open" => open = true,
+            "--no-open" => no_open = true,
+            "--browser" => {
+                let cmd = args.next().ok_or("--browser needs a command")?;
+                browser = Some(cmd);
+                open = true;
+            }
+            "-o" | "--output" => {
+                let path = args.next().ok_or_else(|| format!("{} needs a file name", arg))?;
+                output = Some(PathBuf::from(path));
+            }
+            _ if arg.starts_with("--output=") => {
+                output = Some(PathBuf::from(&arg["--output=".len()..]));
+            }
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                return Err(format!("unknown option '{}'", arg));
+            }
+            _ => {
+                if input.is_some() {
+                    return Err(format!("unexpected extra argument '{}'", arg));
+                }
+                input = Some(PathBuf::from(arg));
+            }
+        }
+    }
+
+    let input = input.ok_or("no input file given")?;
+    check_extension(&input)?;
+    if stdout && output.is_some() {
+        return Err("--stdout and --output cannot be used together".into());
+    }
+    if dump.is_some() && output.is_some() {
+        return Err("--dump-tokens and --dump-ast print to standard output; drop --output".into());
+    }
+
+    // Nothing to open when the HTML goes to stdout, or when no HTML is made.
+    let open = open && !no_open && !stdout && dump.is_none();
+    Ok(Command::Compile(Options { input, output, stdout, format, open, browser, dump }))
+}
+
+/// Reads the value of --format.
+fn parse_format(name: &str) -> Result<HtmlFormat, String> {
+    match name.to_ascii_lowercase().as_str() {
+        "legacy" => Ok(HtmlFormat::Legacy),
+        "html5" => Ok(HtmlFormat::Html5),
+        _ => Err(format!("unknown format '{}' (expected legacy or html5)", name)),
+    }
+}
+
+/// The compiler only accepts lol code files.
+fn check_extension(input: &std::path::Path) -> Result<(), String> {
+    match input.extension().and_then(|e| e.to_str()) {
+        Some(ext) if ext.eq_ignore_ascii_case("lol") => Ok(()),
+        _ => Err(format!("'{}' is not a lol code file (expected a .lol extension)", input.display())),
+    }
+}
+
+/// The exit code for a failed compile, picked from the earliest failing phase.
+pub fn exit_code(kinds: impl IntoIterator<Item = DiagnosticKind>) -> i32 {
+    kinds
+        .into_iter()
+        .map(|k| match k {
+            DiagnosticKind::Lexical => EXIT_LEXICAL,
+            DiagnosticKind::Syntax => EXIT_SYNTAX,
+            DiagnosticKind::Semantic => EXIT_SEMANTIC,
+        })
+        .min()
+        .unwrap_or(EXIT_SYNTAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Result<Command, String> {
+        parse_args(list.iter().map(|s| s.to_string()))
+    }
+
+    fn options(list: &[&str]) -> Options {
+        match args(list) {
+            Ok(Command::Compile(opts)) => opts,
+            other => panic!("expected a compile command, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_input_without_lol_extension() {
+        let err = args(&["notes.txt"]).unwrap_err();
+        assert_eq!(err, "'notes.txt' is not a lol code file (expected a .lol extension)");
+        assert!(args(&["notes"]).is_err());
+        assert_eq!(options(&["Notes.LOL"]).input, PathBuf::from("Notes.LOL"));
+    }
+
+    #[test]
+    fn stdout_and_output_conflict() {
+        let err = args(&["a.lol", "--stdout", "-o", "a.html"]).unwrap_err();
+        assert_eq!(err, "--stdout and --output cannot be used together");
+        assert_eq!(options(&["a.lol", "--output=b.html"]).output, Some(PathBuf::from("b.html")));
+    }
+
+    #[test]
+    fn browser_turns_on_open_and_no_open_wins() {
+        let opts = options(&["a.lol", "--browser", "firefox"]);
+        assert!(opts.open);
+        assert_eq!(opts.browser.as_deref(), Some("firefox"));
+
+        assert!(!options(&["a.lol", "--browser", "firefox", "--no-open"]).open);
+        assert!(!options(&["a.lol", "--no-open", "--open"]).open);
+        assert!(!options(&["a.lol"]).open);
+    }
+
+    #[test]
+    fn values_may_follow_an_equals_sign_or_come_next() {
+        assert_eq!(options(&["a.lol", "--format=html5"]).format, HtmlFormat::Html5);
+        assert_eq!(options(&["a.lol", "--format", "HTML5"]).format, HtmlFormat::Html5);
+        assert!(args(&["a.lol", "--format="]).is_err());
+
+        assert_eq!(options(&["--dump-ast", "json", "a.lol"]).dump, Some(Dump::AstJson));
+        assert_eq!(options(&["a.lol", "--dump-ast=json"]).dump, Some(Dump::AstJson));
+        assert_eq!(options(&["--dump-ast", "a.lol"]).dump, Some(Dump::AstTree));
+    }
+
+    #[test]
+    fn exit_code_picks_the_earliest_phase() {
+        use DiagnosticKind::*;
+        assert_eq!(exit_code([Semantic, Syntax, Lexical]), EXIT_LEXICAL);
+        assert_eq!(exit_code([Semantic, Syntax]), EXIT_SYNTAX);
+        assert_eq!(exit_code([Semantic]), EXIT_SEMANTIC);
+    }
+}
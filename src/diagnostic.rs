@@ -0,0 +1,178 @@
+// diagnostic.rs
+// This file turns compiler errors into readable reports.
+// A report shows the message, the file location, the offending source line
+// with a caret underline, and optional notes pointing at related source
+// (for example, where an unclosed block was opened).
+
+use std::fmt::Write;
+
+use crate::error::LolError;
+use crate::span::Span;
+
+// ANSI escape codes used when color output is enabled.
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// Which compiler phase found the problem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    Lexical,
+    Syntax,
+    Semantic,
+}
+
+impl DiagnosticKind {
+    /// The heading printed in front of the message.
+    pub fn title(self) -> &'static str {
+        match self {
+            DiagnosticKind::Lexical => "lexical error",
+            DiagnosticKind::Syntax => "syntax error",
+            DiagnosticKind::Semantic => "semantic error",
+        }
+    }
+}
+
+/// How serious a problem is. Only errors stop the compile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A renderable report about a problem in the source.
+/// - kind: which phase found the problem
+/// - severity: whether the problem is an error or only a warning
+/// - message: one-line description
+/// - span/label: the primary location and the text printed under it
+/// - notes: secondary locations with their own labels
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    pub label: String,
+    pub notes: Vec<(Span, String)>,
+}
+
+impl From<&LolError> for Diagnostic {
+    fn from(err: &LolError) -> Self {
+        match err {
+            LolError::Lexical { span, msg } => Diagnostic {
+                kind: DiagnosticKind::Lexical,
+                severity: Severity::Error,
+                message: msg.clone(),
+                span: span.clone(),
+                label: String::new(),
+                notes: vec![],
+            },
+            LolError::Syntax { span, expected, found, opened } => Diagnostic {
+                kind: DiagnosticKind::Syntax,
+                severity: Severity::Error,
+                message: format!("expected {}, found {}", expected, found),
+                span: span.clone(),
+                label: format!("expected {} here", expected),
+                notes: opened
+                    .iter()
+                    .map(|o| (o.0.clone(), format!("this {} was opened here", o.1)))
+                    .collect(),
+            },
+            LolError::Semantic { span, msg, note } => Diagnostic {
+                kind: DiagnosticKind::Semantic,
+                severity: Severity::Error,
+                message: msg.clone(),
+                span: span.clone(),
+                label: String::new(),
+                notes: note.iter().map(|n| (n.0.clone(), n.1.clone())).collect(),
+            },
+        }
+    }
+}
+
+impl Diagnostic {
+    /// A warning found by `kind`'s phase at `span`; warnings never stop the compile.
+    pub fn warning(kind: DiagnosticKind, span: Span, message: String) -> Self {
+        Diagnostic { kind, severity: Severity::Warning, message, span, label: String::new(), notes: vec![] }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Renders the diagnostic against `source`, optionally with ANSI colors.
+    pub fn render(&self, source: &str, color: bool) -> String {
+        // Width of the line-number gutter, so all `|` bars line up.
+        let gutter = std::iter::once(&self.span)
+            .chain(self.notes.iter().map(|(s, _)| s))
+            .map(|s| s.line.to_string().len())
+            .max()
+            .unwrap_or(1);
+
+        let mut r = Renderer { source, color, gutter, out: String::new() };
+        let (mark_color, title) = match self.severity {
+            Severity::Error => (RED, self.kind.title()),
+            Severity::Warning => (YELLOW, "warning"),
+        };
+        let (head, blue, bold, reset) = (r.paint(mark_color), r.paint(BLUE), r.paint(BOLD), r.paint(RESET));
+        let pad = " ".repeat(gutter);
+
+        let _ = writeln!(r.out, "{}{}{}: {}{}{}", head, title, reset, bold, self.message, reset);
+        let _ = writeln!(r.out, "{}{}-->{} {}", pad, blue, reset, self.span);
+        let _ = writeln!(r.out, "{} {}|{}", pad, blue, reset);
+
+        r.snippet(&self.span, '^', mark_color, &self.label);
+        for (span, note) in &self.notes {
+            r.snippet(span, '-', BLUE, &format!("note: {}", note));
+        }
+        r.out
+    }
+}
+
+/// Accumulates the rendered text of one diagnostic.
+struct Renderer<'a> {
+    source: &'a str,
+    color: bool,
+    gutter: usize,
+    out: String,
+}
+
+impl Renderer<'_> {
+    /// Returns the escape code when color is on, or nothing when it is off.
+    fn paint(&self, code: &'static str) -> &'static str {
+        if self.color { code } else { "" }
+    }
+
+    /// Writes one source line with `mark` characters under the span.
+    fn snippet(&mut self, span: &Span, mark: char, mark_color: &'static str, label: &str) {
+        let source = self.source;
+        let gutter = self.gutter;
+        let (mark_color, blue, reset) = (self.paint(mark_color), self.paint(BLUE), self.paint(RESET));
+
+        // Find the full line that contains the start of the span.
+        let start = span.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..].find('\n').map_or(source.len(), |i| start + i);
+        let line = source[line_start..line_end].trim_end_matches('\r');
+
+        // Keep tabs in the padding so the marks line up with the printed line.
+        let lead: String = source[line_start..start]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        // Underline the span, but never past the end of its first line.
+        let end = span.end.clamp(start, line_end);
+        let width = source[start..end].chars().count().max(1);
+        let marks = mark.to_string().repeat(width);
+
+        let _ = writeln!(self.out, "{:>gutter$} {}|{} {}", span.line, blue, reset, line);
+        let _ = write!(self.out, "{:gutter$} {}|{} {}{}{}{}", "", blue, reset, lead, mark_color, marks, reset);
+        if !label.is_empty() {
+            let _ = write!(self.out, " {}{}{}", mark_color, label, reset);
+        }
+        self.out.push('\n');
+    }
+}
@@ -0,0 +1,188 @@
+// dump.rs
+// This file prints the output of the first stages for debugging:
+// - tokens: one token per line, with its line:col
+// - tree:   the AST as an indented tree
+// - json:   the AST as JSON, for other tools
+// The CLI uses these for --dump-tokens and --dump-ast.
+
+use crate::ast::{Node, NodeKind};
+use crate::diagnostic::Diagnostic;
+use crate::lexer::CharLexer;
+use crate::token::TokenKind;
+use crate::Diagnostics;
+
+/// One value stored in a node (besides its children).
+enum Field<'n> {
+    Str(&'n str),
+    Num(u32),
+    Bool(bool),
+    Null,
+}
+
+/// Lexes `source` and lists its tokens, ending with `Eof`.
+/// Lexical errors are returned alongside; the lexer keeps going after them.
+pub fn tokens(source: &str, file: &str) -> (String, Diagnostics) {
+    let mut lexer = CharLexer::new(source, file);
+    let mut out = String::new();
+    let mut errors = vec![];
+    loop {
+        let token = match lexer.next_token() {
+            Ok(t) => t,
+            Err(e) => {
+                errors.push(Diagnostic::from(&e));
+                continue;
+            }
+        };
+        let (kind, text) = match token.kind {
+            TokenKind::Hash => ("Hash", "#".to_string()),
+            TokenKind::Word(w) => ("Word", format!("{:?}", w)),
+            TokenKind::Text(t) => ("Text", format!("{:?}", t)),
+            TokenKind::Kw(k) => ("Kw", k.as_str().to_string()),
+            TokenKind::Escaped(e) => ("Escaped", format!("{:?}", e)),
+            TokenKind::Raw(r) => ("Raw", format!("{:?}", r)),
+            TokenKind::Eof => ("Eof", String::new()),
+        };
+        let at = format!("{}:{}", token.span.line, token.span.col);
+        out.push_str(format!("{:<8} {:<8} {}", at, kind, text).trim_end());
+        out.push('\n');
+        if token.kind == TokenKind::Eof {
+            break;
+        }
+    }
+    (out, errors)
+}
+
+/// The AST as an indented tree, one node per line:
+/// its kind, where it starts, then its fields.
+pub fn tree(ast: &[Node]) -> String {
+    let mut out = String::new();
+    for node in ast {
+        write_tree(&mut out, node, 0);
+    }
+    out
+}
+
+fn write_tree(out: &mut String, node: &Node, depth: usize) {
+    let (name, fields, children) = describe(&node.kind);
+    out.push_str(&format!("{:indent$}{} @{}:{}", "", name, node.span.line, node.span.col, indent = depth * 2));
+    for (key, value) in fields {
+        let value = match value {
+            Field::Str(s) => format!("{:?}", s),
+            Field::Num(n) => n.to_string(),
+            Field::Bool(b) => b.to_string(),
+            Field::Null => "none".to_string(),
+        };
+        out.push_str(&format!(" {}={}", key, value));
+    }
+    out.push('\n');
+    for child in children {
+        write_tree(out, child, depth + 1);
+    }
+}
+
+/// The AST as a JSON array of nodes. Each node is an object with its
+/// "kind", "line" and "col", its fields, and "children" when it has any.
+pub fn json(ast: &[Node]) -> String {
+    let mut out = String::new();
+    write_json_list(&mut out, ast, 0);
+    out.push('\n');
+    out
+}
+
+fn write_json_list(out: &mut String, nodes: &[Node], depth: usize) {
+    if nodes.is_empty() {
+        out.push_str("[]");
+        return;
+    }
+    out.push_str("[\n");
+    for (i, node) in nodes.iter().enumerate() {
+        out.push_str(&"  ".repeat(depth + 1));
+        write_json(out, node, depth + 1);
+        out.push_str(if i + 1 < nodes.len() { ",\n" } else { "\n" });
+    }
+    out.push_str(&"  ".repeat(depth));
+    out.push(']');
+}
+
+fn write_json(out: &mut String, node: &Node, depth: usize) {
+    let (name, fields, children) = describe(&node.kind);
+    out.push_str(&format!("{{\"kind\": {}, \"line\": {}, \"col\": {}", json_str(name), node.span.line, node.span.col));
+    for (key, value) in fields {
+        let value = match value {
+            Field::Str(s) => json_str(s),
+            Field::Num(n) => n.to_string(),
+            Field::Bool(b) => b.to_string(),
+            Field::Null => "null".to_string(),
+        };
+        out.push_str(&format!(", {}: {}", json_str(key), value));
+    }
+    if !children.is_empty() {
+        out.push_str(", \"children\": ");
+        write_json_list(out, children, depth);
+    }
+    out.push('}');
+}
+
+/// Quotes `s` as a JSON string.
+fn json_str(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Splits a node into its kind's name, its fields and its children,
+/// so the tree and JSON forms list the same things.
+fn describe(kind: &NodeKind) -> (&'static str, Vec<(&'static str, Field<'_>)>, &[Node]) {
+    use Field::*;
+    match kind {
+        NodeKind::Html(c) => ("Html", vec![], c),
+        NodeKind::Comment(t) => ("Comment", vec![("text", Str(t))], &[]),
+        NodeKind::Head(c) => ("Head", vec![], c),
+        NodeKind::Title(t) => ("Title", vec![("text", Str(t))], &[]),
+        NodeKind::Body(c) => ("Body", vec![], c),
+        NodeKind::Paragraph(c) => ("Paragraph", vec![], c),
+        NodeKind::Heading { level, id, children } => {
+            ("Heading", vec![("level", Num(*level)), ("id", Str(id))], children)
+        }
+        NodeKind::Section { title, level, id, children } => (
+            "Section",
+            vec![("title", Str(title)), ("level", Num(u32::from(*level))), ("id", Str(id))],
+            children,
+        ),
+        NodeKind::Toc => ("Toc", vec![], &[]),
+        NodeKind::Bold(c) => ("Bold", vec![], c),
+        NodeKind::Italics(c) => ("Italics", vec![], c),
+        NodeKind::Link { href, children } => ("Link", vec![("href", Str(href))], children),
+        NodeKind::List(c) => ("List", vec![], c),
+        NodeKind::OrderedList { start, style, items } => {
+            let start = start.map_or(Null, Num);
+            ("OrderedList", vec![("start", start), ("type", Str(style.html_type().unwrap_or("1")))], items)
+        }
+        NodeKind::ListItem(c) => ("ListItem", vec![], c),
+        NodeKind::Table(c) => ("Table", vec![], c),
+        NodeKind::TableRow(c) => ("TableRow", vec![], c),
+        NodeKind::TableCell { header, children } => ("TableCell", vec![("header", Bool(*header))], children),
+        NodeKind::CodeBlock { lang, text } => ("CodeBlock", vec![("lang", Str(lang)), ("text", Str(text))], &[]),
+        NodeKind::Newline => ("Newline", vec![], &[]),
+        NodeKind::Audio(src) => ("Audio", vec![("src", Str(src))], &[]),
+        NodeKind::Video(src) => ("Video", vec![("src", Str(src))], &[]),
+        NodeKind::Image { src, alt } => ("Image", vec![("src", Str(src)), ("alt", Str(alt))], &[]),
+        NodeKind::Text(t) => ("Text", vec![("text", Str(t))], &[]),
+        NodeKind::Literal(t) => ("Literal", vec![("text", Str(t))], &[]),
+        NodeKind::VarDef { name, value } => ("VarDef", vec![("name", Str(name)), ("value", Str(value))], &[]),
+        NodeKind::VarUse { name } => ("VarUse", vec![("name", Str(name))], &[]),
+        NodeKind::Error => ("Error", vec![], &[]),
+    }
+}
@@ -4,32 +4,37 @@
 // - Lexical: bad tokens
 // - Syntax: grammar mismatch
 // - Semantic: variable scope issues
+// Every error carries the Span of the source that caused it.
 
 use std::error::Error;
 use std::fmt;
 
+use crate::span::Span;
+
 #[derive(Debug)]
 pub enum LolError {
     // Produced in the lexer when we see an invalid or unknown token.
-    Lexical { line: usize, col: usize, msg: String },
+    Lexical { span: Span, msg: String },
 
     // Produced in the parser when the token does not match the grammar.
-    Syntax  { expected: String, found: String },
+    // `opened` names the enclosing block (e.g. "#MAEK PARAGRAF") and where it began.
+    Syntax  { span: Span, expected: String, found: String, opened: Option<Box<(Span, String)>> },
 
     // Produced during static scope checking (e.g., variable not defined).
-    Semantic(String),
+    // `note` points at a related place in the source, with what it shows.
+    Semantic { span: Span, msg: String, note: Option<Box<(Span, String)>> },
 }
 
 impl fmt::Display for LolError {
-    // Formats the error message in a readable form.
+    // Formats the error message in a readable form, prefixed by `file:line:col`.
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            LolError::Lexical { line, col, msg } =>
-                write!(f, "Lexical error at line {}, col {}: {}", line, col, msg),
-            LolError::Syntax { expected, found } =>
-                write!(f, "Syntax error: expected {}, found {}", expected, found),
-            LolError::Semantic(s) =>
-                write!(f, "Static semantic error: {}", s),
+            LolError::Lexical { span, msg } =>
+                write!(f, "{}: Lexical error: {}", span, msg),
+            LolError::Syntax { span, expected, found, .. } =>
+                write!(f, "{}: Syntax error: expected {}, found {}", span, expected, found),
+            LolError::Semantic { span, msg, .. } =>
+                write!(f, "{}: Static semantic error: {}", span, msg),
         }
     }
 }
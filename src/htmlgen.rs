@@ -2,112 +2,293 @@
 // This module takes the AST and turns it into HTML text.
 // We walk through the AST nodes and emit HTML tags based on node type.
 
-use crate::ast::Node;
+use crate::ast::{Node, NodeKind};
 
-pub struct HtmlGen;
+/// Which flavor of HTML to produce.
+/// - Legacy: the original bare `<html>` output
+/// - Html5: a standards-compliant document with doctype, charset and `<body>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HtmlFormat {
+    #[default]
+    Legacy,
+    Html5,
+}
+
+pub struct HtmlGen {
+    format: HtmlFormat,
+}
 
 impl HtmlGen {
-    // Create a new HTML generator (no state needed).
-    pub fn new() -> Self { Self }
+    // Create a new HTML generator producing the given format.
+    pub fn new(format: HtmlFormat) -> Self { Self { format } }
 
     // Entry function: takes the AST and returns a full HTML string.
-    pub fn generate(&mut self, ast: &Vec<Node>) -> String {
+    pub fn generate(&mut self, ast: &[Node]) -> String {
+        if self.format == HtmlFormat::Html5 {
+            return self.generate_html5(ast);
+        }
         let mut out = String::from("<html>\n");
         self.emit_nodes(ast, &mut out, 1);
         out.push_str("</html>\n");
         out
     }
 
+    // HTML5 document: comments that open the file stay before <head>, every
+    // HEAD block is merged into one <head> (created if the file has none),
+    // and everything else goes in <body>. The page gets exactly one <title>,
+    // wherever its GIMMEH TITLE was written.
+    fn generate_html5(&self, ast: &[Node]) -> String {
+        let mut out = String::from("<!DOCTYPE html>\n<html lang=\"en\">\n");
+
+        let leading = ast.iter().take_while(|n| matches!(n.kind, NodeKind::Comment(_))).count();
+        self.emit_nodes(&ast[..leading], &mut out, 1);
+
+        let heads: Vec<&[Node]> = ast
+            .iter()
+            .filter_map(|n| match &n.kind { NodeKind::Head(kids) => Some(kids.as_slice()), _ => None })
+            .collect();
+
+        out.push_str(&format!("{}<head>\n", Self::indent(1)));
+        out.push_str(&format!("{}<meta charset=\"utf-8\">\n", Self::indent(2)));
+        // <title> is required in HTML5, and only one is allowed.
+        let title = find_title(ast).map_or("Untitled".to_string(), escape_text);
+        out.push_str(&format!("{}<title>{}</title>\n", Self::indent(2), title));
+        for kids in heads {
+            self.emit_nodes(kids, &mut out, 2);
+        }
+        out.push_str(&format!("{}</head>\n", Self::indent(1)));
+
+        out.push_str(&format!("{}<body>\n", Self::indent(1)));
+        for node in &ast[leading..] {
+            if !matches!(node.kind, NodeKind::Head(_)) {
+                self.emit_nodes(std::slice::from_ref(node), &mut out, 2);
+            }
+        }
+        out.push_str(&format!("{}</body>\n", Self::indent(1)));
+        out.push_str("</html>\n");
+        out
+    }
+
+    // <iframe> must be closed with </iframe> in HTML5; legacy output self-closes it.
+    fn iframe(&self, url: &str) -> String {
+        match self.format {
+            HtmlFormat::Legacy => format!("<iframe src=\"{}\"/>", escape_url(url)),
+            HtmlFormat::Html5 => format!("<iframe src=\"{}\"></iframe>", escape_url(url)),
+        }
+    }
+
+    // Block nodes that may not appear inside <p> or inline text.
+    fn is_block(node: &Node) -> bool {
+        matches!(node.kind, NodeKind::List(_) | NodeKind::OrderedList { .. })
+    }
+
+    // A table row whose cells are all header cells.
+    fn is_header_row(node: &Node) -> bool {
+        match &node.kind {
+            NodeKind::TableRow(cells) => cells
+                .iter()
+                .filter(|c| Self::is_visible(c))
+                .all(|c| matches!(c.kind, NodeKind::TableCell { header: true, .. })),
+            _ => false,
+        }
+    }
+
+    // Whether a node produces any output when written inline.
+    fn is_visible(node: &Node) -> bool {
+        !matches!(
+            node.kind,
+            NodeKind::VarDef { .. } | NodeKind::Comment(_) | NodeKind::Title(_) | NodeKind::Error
+        )
+    }
+
     // Small helper for indentation in formatted output.
     fn indent(n: usize) -> String { "    ".repeat(n) }
 
     // Emit a list of nodes, respecting indentation for block-level HTML.
     fn emit_nodes(&self, nodes: &[Node], out: &mut String, level: usize) {
         for node in nodes {
-            match node {
+            match &node.kind {
                 // If there's a root Html(...) node, just emit its children.
-                Node::Html(kids) => self.emit_nodes(kids, out, level),
+                NodeKind::Html(kids) => self.emit_nodes(kids, out, level),
 
                 // HTML comment
-                Node::Comment(t) => {
-                    out.push_str(&format!("{}<!-- {} -->\n", Self::indent(level), t.trim()));
+                NodeKind::Comment(t) => {
+                    out.push_str(&format!("{}<!-- {} -->\n", Self::indent(level), escape_comment(t.trim())));
                 }
 
                 // <head>...</head>
-                Node::Head(kids) => {
+                NodeKind::Head(kids) => {
                     out.push_str(&format!("{}<head>\n", Self::indent(level)));
                     self.emit_nodes(kids, out, level + 1);
                     out.push_str(&format!("{}{}</head>\n", Self::indent(level), ""));
                 }
 
                 // <title>text</title>
-                Node::Title(t) => {
-                    out.push_str(&format!("{}<title> {} </title>\n", Self::indent(level), t.trim()));
+                // HTML5 writes the one title itself, at the top of <head>.
+                NodeKind::Title(t) => {
+                    if self.format == HtmlFormat::Legacy {
+                        out.push_str(&format!("{}<title> {} </title>\n", Self::indent(level), escape_text(t)));
+                    }
                 }
 
                 // <p> ... </p>
-                Node::Paragraph(kids) => {
-                    out.push_str(&format!("{}<p> ", Self::indent(level)));
-                    self.emit_nodes_inline(kids, out);
-                    out.push_str("</p>\n");
+                // A <ul> cannot live inside a <p>, so the paragraph is closed
+                // before each nested list and reopened after it.
+                NodeKind::Paragraph(kids) => {
+                    let mut open = false;
+                    for kid in kids {
+                        if Self::is_block(kid) {
+                            if open {
+                                out.push_str("</p>\n");
+                                open = false;
+                            }
+                            self.emit_nodes(std::slice::from_ref(kid), out, level);
+                        } else if open || Self::is_visible(kid) {
+                            if !open {
+                                out.push_str(&format!("{}<p> ", Self::indent(level)));
+                                open = true;
+                            }
+                            self.emit_nodes_inline(std::slice::from_ref(kid), out);
+                        }
+                    }
+                    if open {
+                        out.push_str("</p>\n");
+                    }
                 }
 
-                // <b>text</b>
-                Node::Bold(t) => {
-                    out.push_str(&format!("<b> {} </b>", t.trim()));
+                // <b>, <i> and <a> are written the same way as inside a paragraph
+                NodeKind::Bold(_) | NodeKind::Italics(_) | NodeKind::Link { .. } => {
+                    self.emit_nodes_inline(std::slice::from_ref(node), out);
                 }
 
-                // <i>text</i>
-                Node::Italics(t) => {
-                    out.push_str(&format!("<i> {} </i>", t.trim()));
+                // <h1 id="slug">...</h1>
+                NodeKind::Heading { level: n, id, children } => {
+                    out.push_str(&format!("{}<h{} id=\"{}\">", Self::indent(level), n, escape_attr(id)));
+                    self.emit_nodes_inline(children, out);
+                    out.push_str(&format!("</h{}>\n", n));
+                }
+
+                // <section id="slug"><h2>title</h2> ... </section>
+                NodeKind::Section { title, level: n, id, children } => {
+                    out.push_str(&format!("{}<section id=\"{}\">\n", Self::indent(level), escape_attr(id)));
+                    out.push_str(&format!("{}<h{}>{}</h{}>\n", Self::indent(level + 1), n, escape_text(title), n));
+                    self.emit_nodes(children, out, level + 1);
+                    out.push_str(&format!("{}</section>\n", Self::indent(level)));
+                }
+
+                // <pre><code class="language-x">...</code></pre>; the text is written
+                // unindented since <pre> keeps every space.
+                NodeKind::CodeBlock { lang, text } => {
+                    let class = if lang.is_empty() {
+                        String::new()
+                    } else {
+                        format!(" class=\"language-{}\"", escape_attr(lang))
+                    };
+                    out.push_str(&format!("{}<pre><code{}>{}</code></pre>\n", Self::indent(level), class, escape_text(text)));
                 }
 
                 // <br>
-                Node::Newline => {
+                NodeKind::Newline => {
                     out.push_str("<br>\n");
                 }
 
                 // <ul> ... </ul>
-                Node::List(items) => {
+                NodeKind::List(items) => {
                     out.push_str(&format!("{}<ul>\n", Self::indent(level)));
                     self.emit_nodes(items, out, level + 1);
                     out.push_str(&format!("{}{}</ul>\n", Self::indent(level), ""));
                 }
 
+                // <ol start="n" type="i"> ... </ol>
+                NodeKind::OrderedList { start, style, items } => {
+                    let mut attrs = String::new();
+                    if let Some(n) = start {
+                        attrs.push_str(&format!(" start=\"{}\"", n));
+                    }
+                    if let Some(t) = style.html_type() {
+                        attrs.push_str(&format!(" type=\"{}\"", t));
+                    }
+                    out.push_str(&format!("{}<ol{}>\n", Self::indent(level), attrs));
+                    self.emit_nodes(items, out, level + 1);
+                    out.push_str(&format!("{}</ol>\n", Self::indent(level)));
+                }
+
+                // <table> with the leading all-header rows in <thead>, the rest in <tbody>
+                NodeKind::Table(rows) => {
+                    let head = rows.iter().take_while(|r| Self::is_header_row(r)).count();
+                    out.push_str(&format!("{}<table>\n", Self::indent(level)));
+                    for (tag, part) in [("thead", &rows[..head]), ("tbody", &rows[head..])] {
+                        if part.iter().any(|r| matches!(r.kind, NodeKind::TableRow(_))) {
+                            out.push_str(&format!("{}<{}>\n", Self::indent(level + 1), tag));
+                            self.emit_nodes(part, out, level + 2);
+                            out.push_str(&format!("{}</{}>\n", Self::indent(level + 1), tag));
+                        }
+                    }
+                    out.push_str(&format!("{}</table>\n", Self::indent(level)));
+                }
+
+                // <tr> ... </tr>
+                NodeKind::TableRow(cells) => {
+                    out.push_str(&format!("{}<tr>\n", Self::indent(level)));
+                    self.emit_nodes(cells, out, level + 1);
+                    out.push_str(&format!("{}</tr>\n", Self::indent(level)));
+                }
+
+                // <th> or <td>; cell contents are written inline like a list item
+                NodeKind::TableCell { header, children } => {
+                    let tag = if *header { "th" } else { "td" };
+                    out.push_str(&format!("{}<{}>", Self::indent(level), tag));
+                    self.emit_nodes_inline(children, out);
+                    out.push_str(&format!("</{}>\n", tag));
+                }
+
                 // <li> ... </li>
-                Node::ListItem(kids) => {
+                // Nested lists go on their own lines inside the <li>.
+                NodeKind::ListItem(kids) => {
                     out.push_str(&format!("{}<li> ", Self::indent(level)));
-                    self.emit_nodes_inline(kids, out);
+                    for kid in kids {
+                        if Self::is_block(kid) {
+                            out.push('\n');
+                            self.emit_nodes(std::slice::from_ref(kid), out, level + 1);
+                            out.push_str(&Self::indent(level));
+                        } else {
+                            self.emit_nodes_inline(std::slice::from_ref(kid), out);
+                        }
+                    }
                     out.push_str("</li>\n");
                 }
 
                 // Audio element
-                Node::Audio(url) => {
+                NodeKind::Audio(url) => {
                     out.push_str(&format!(
                         "{}<audio controls>\n{}<source src=\"{}\">\n{}</audio>\n",
                         Self::indent(level),
                         Self::indent(level + 1),
-                        url.trim(),
+                        escape_url(url.trim()),
                         Self::indent(level)
                     ));
                 }
 
                 // Video (YouTube iframe)
-                Node::Video(url) => {
-                    out.push_str(&format!(
-                        "{}<iframe src=\"{}\"/>\n",
-                        Self::indent(level),
-                        url.trim()
-                    ));
+                NodeKind::Video(url) => {
+                    out.push_str(&format!("{}{}\n", Self::indent(level), self.iframe(url.trim())));
+                }
+
+                // <img>
+                NodeKind::Image { .. } => {
+                    out.push_str(&Self::indent(level));
+                    self.emit_nodes_inline(std::slice::from_ref(node), out);
+                    out.push('\n');
                 }
 
                 // Regular text inside blocks
-                Node::Text(t) => {
-                    out.push_str(t);
+                NodeKind::Text(t) | NodeKind::Literal(t) => {
+                    out.push_str(&escape_text(t));
                 }
 
                 // These nodes are handled earlier in semantic stage, so we skip here.
-                Node::VarDef { .. } | Node::VarUse { .. } | Node::Body(_) => { }
+                NodeKind::VarDef { .. } | NodeKind::VarUse { .. } | NodeKind::Body(_) |
+                NodeKind::Toc | NodeKind::Error => { }
             }
         }
     }
@@ -115,30 +296,154 @@ impl HtmlGen {
     // Inline writer: used inside <p> and <li> so we don't insert new lines unnecessarily.
     fn emit_nodes_inline(&self, nodes: &[Node], out: &mut String) {
         for node in nodes {
-            match node {
-                Node::Bold(t)    => out.push_str(&format!("<b> {} </b>", t.trim())),
-                Node::Italics(t) => out.push_str(&format!("<i> {} </i>", t.trim())),
-                Node::Newline    => out.push_str("<br>\n"),
-                Node::Text(t)    => out.push_str(t),
-
-                Node::Audio(u)   => out.push_str(&format!(
-                    "<audio controls><source src=\"{}\"></audio>", u.trim()
+            match &node.kind {
+                NodeKind::Bold(k) => {
+                    out.push_str("<b> ");
+                    self.emit_nodes_inline(k, out);
+                    out.push_str(" </b>");
+                }
+                NodeKind::Italics(k) => {
+                    out.push_str("<i> ");
+                    self.emit_nodes_inline(k, out);
+                    out.push_str(" </i>");
+                }
+                // <a href="...">label</a>; a link without a label shows its address
+                NodeKind::Link { href, children } => {
+                    out.push_str(&format!("<a href=\"{}\">", escape_url(href)));
+                    if children.is_empty() {
+                        out.push_str(&escape_text(href));
+                    } else {
+                        self.emit_nodes_inline(children, out);
+                    }
+                    out.push_str("</a>");
+                }
+                NodeKind::Newline    => out.push_str("<br>\n"),
+                NodeKind::Text(t) | NodeKind::Literal(t) => out.push_str(&escape_text(t)),
+                NodeKind::CodeBlock { text, .. } => out.push_str(&escape_text(text)),
+
+                NodeKind::Audio(u)   => out.push_str(&format!(
+                    "<audio controls><source src=\"{}\"></audio>", escape_url(u.trim())
                 )),
 
-                Node::Video(u)   => out.push_str(&format!(
-                    "<iframe src=\"{}\"/>", u.trim()
+                NodeKind::Video(u)   => out.push_str(&self.iframe(u.trim())),
+
+                NodeKind::Image { src, alt } => out.push_str(&format!(
+                    "<img src=\"{}\" alt=\"{}\">", escape_url(src), escape_attr(alt)
                 )),
 
                 // If nested blocks somehow end up inline, flatten them.
-                Node::ListItem(k) | Node::Paragraph(k) | Node::Html(k) |
-                Node::List(k) | Node::Head(k) | Node::Body(k) => {
+                NodeKind::ListItem(k) | NodeKind::Paragraph(k) | NodeKind::Html(k) |
+                NodeKind::Heading { children: k, .. } | NodeKind::Section { children: k, .. } |
+                NodeKind::Table(k) | NodeKind::TableRow(k) | NodeKind::TableCell { children: k, .. } |
+                NodeKind::List(k) | NodeKind::Head(k) | NodeKind::Body(k) |
+                NodeKind::OrderedList { items: k, .. } => {
                     self.emit_nodes_inline(k, out);
                 }
 
                 // Ignore nodes that don't belong inline.
-                Node::Title(_) | Node::Comment(_) |
-                Node::VarDef { .. } | Node::VarUse { .. } => { }
+                NodeKind::Title(_) | NodeKind::Comment(_) |
+                NodeKind::VarDef { .. } | NodeKind::VarUse { .. } | NodeKind::Toc | NodeKind::Error => { }
             }
         }
     }
 }
+
+/// The page title: the first GIMMEH TITLE, in the head, at the top level or in a section.
+fn find_title(nodes: &[Node]) -> Option<&str> {
+    nodes.iter().find_map(|n| match &n.kind {
+        NodeKind::Title(t) => Some(t.as_str()),
+        NodeKind::Html(kids) | NodeKind::Head(kids) | NodeKind::Body(kids) => find_title(kids),
+        NodeKind::Section { children, .. } => find_title(children),
+        _ => None,
+    })
+}
+
+// Escaping helpers. Each HTML context has its own rules, so text is always
+// escaped for the place it is written to.
+
+/// Escapes text placed between tags.
+fn escape_text(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Escapes text placed inside a double- or single-quoted attribute value.
+fn escape_attr(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Makes text safe inside `<!-- ... -->`: a comment may not contain "--",
+/// which would let "-->" end it early.
+fn escape_comment(s: &str) -> String {
+    let mut out = s.to_string();
+    while out.contains("--") {
+        out = out.replace("--", "- -");
+    }
+    out
+}
+
+/// Percent-encodes characters that are not allowed in a URL (spaces, quotes,
+/// non-ASCII, ...), then escapes the result for use as an attribute value.
+fn escape_url(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        let c = b as char;
+        if c.is_ascii_alphanumeric() || "-._~:/?#[]@!$&'()*+,;=%".contains(c) {
+            out.push(c);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    escape_attr(&out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_escapes_markup_characters() {
+        assert_eq!(escape_text("1 < 2 & 3 > 0"), "1 &lt; 2 &amp; 3 &gt; 0");
+        assert_eq!(escape_text("<script>"), "&lt;script&gt;");
+        assert_eq!(escape_text("say \"hi\""), "say \"hi\"");
+    }
+
+    #[test]
+    fn attributes_escape_quotes() {
+        assert_eq!(escape_attr("a \"fat\" cat's"), "a &quot;fat&quot; cat&#39;s");
+        assert_eq!(escape_attr("x\" onerror=\"y"), "x&quot; onerror=&quot;y");
+    }
+
+    #[test]
+    fn comments_cannot_be_closed_early() {
+        assert_eq!(escape_comment("a --> b"), "a - -> b");
+        assert_eq!(escape_comment("---"), "- - -");
+        assert!(!escape_comment("----->").contains("--"));
+    }
+
+    #[test]
+    fn urls_encode_spaces_and_quotes() {
+        assert_eq!(escape_url("my song.mp3"), "my%20song.mp3");
+        assert_eq!(escape_url("a.mp3\" onload=\"x"), "a.mp3%22%20onload=%22x");
+        assert_eq!(escape_url("https://x.test/?q=1&r=2"), "https://x.test/?q=1&amp;r=2");
+    }
+}
@@ -1,22 +1,29 @@
 // lexer.rs
 // This file performs lexical analysis (tokenizing).
-// It reads the raw input text character-by-character and produces Tokens.
+// It walks the raw input text character-by-character and produces Tokens
+// whose text borrows from the input, so lexing allocates nothing per token.
 // The parser uses these Tokens to build the AST.
 
-use crate::error::Result;
-use crate::token::{Kw, Token};
+use std::rc::Rc;
+
+use crate::error::{LolError, Result};
+use crate::span::Span;
+use crate::token::{lookup, Kw, Token, TokenKind, KEYWORDS};
 
 /// A minimal lexer trait (kept only to match the project spec)
+#[allow(dead_code)]
 pub trait LexicalAnalyzer {
     fn get_char(&mut self) -> char;
     fn add_char(&mut self, _c: char);
     fn lookup(&self, s: &str) -> bool;
 }
 
-/// Character-by-character lexer.
-pub struct CharLexer {
-    chars: Vec<char>, // full input as characters
-    pos: usize,       // current index into chars
+/// Character-by-character lexer over a borrowed source.
+/// Also an `Iterator` over the tokens, ending before `Eof`.
+pub struct CharLexer<'a> {
+    src: &'a str,     // full input
+    pos: usize,       // current byte offset into src
+    file: Rc<str>,    // name of the file being lexed (for spans)
     pub line: usize,  // current line (for error reporting)
     pub col: usize,   // current column (for error reporting)
 
@@ -26,27 +33,41 @@ pub struct CharLexer {
     // tracks the last keyword, to determine if the next word
     // *must* be another keyword (e.g., after MAEK or GIMMEH)
     prev_kw: Option<Kw>,
+
+    // true right after KODE: the next token is the block's verbatim body
+    raw_next: bool,
+
+    // true once Eof has been returned by the iterator
+    done: bool,
 }
 
-impl CharLexer {
+impl<'a> CharLexer<'a> {
     /// Construct a new lexer from the input source text.
-    pub fn new(input: &str) -> Self {
+    /// `file` is the name recorded in every token's span.
+    pub fn new(input: &'a str, file: &str) -> Self {
         Self {
-            chars: input.chars().collect(),
+            src: input,
             pos: 0,
+            file: Rc::from(file),
             line: 1,
             col: 0,
             after_hash: false,
             prev_kw: None,
+            raw_next: false,
+            done: false,
         }
     }
 
     #[inline]
-    fn eof(&self) -> bool { self.pos >= self.chars.len() }
+    fn eof(&self) -> bool { self.pos >= self.src.len() }
 
     #[inline]
     fn peek(&self) -> char {
-        if self.eof() { '\0' } else { self.chars[self.pos] }
+        match self.src.as_bytes().get(self.pos) {
+            Some(&b) if b.is_ascii() => b as char,
+            Some(_) => self.src[self.pos..].chars().next().unwrap_or('\0'),
+            None => '\0',
+        }
     }
 
     /// Move forward one character and return it.
@@ -61,18 +82,18 @@ impl CharLexer {
             } else {
                 self.col += 1;
             }
-            self.pos += 1;
+            self.pos += c.len_utf8();
         }
         c
     }
 
-    /// Read characters while `pred` is true, return them as a string.
-    fn take_while<F: Fn(char) -> bool>(&mut self, pred: F) -> String {
-        let mut s = String::new();
+    /// Read characters while `pred` is true, return them as a slice of the input.
+    fn take_while<F: Fn(char) -> bool>(&mut self, pred: F) -> &'a str {
+        let start = self.pos;
         while !self.eof() && pred(self.peek()) {
-            s.push(self.bump());
+            self.bump();
         }
-        s
+        &self.src[start..self.pos]
     }
 
     /// Identifiers and words: letters, numbers, underscore.
@@ -85,47 +106,70 @@ impl CharLexer {
         matches!(c, ',' | '.' | '"' | ':' | '?' | '!' | '%' | '/' )
     }
 
-    /// Convert a string to a keyword if it matches.
-    fn map_kw(upper: &str) -> Option<Kw> {
-        use Kw::*;
-        match upper {
-            "HAI" => Some(Hai),
-            "KTHXBYE" => Some(Kthxbye),
-            "OBTW" => Some(OBTW),
-            "TLDR" => Some(TLDR),
-            "MAEK" => Some(Maek),
-            "GIMMEH" => Some(Gimmeh),
-            "HEAD" => Some(Head),
-            "TITLE" => Some(Title),
-            "PARAGRAF" => Some(Paragraf),
-            "OIC" => Some(OIC),
-            "BOLD" => Some(Bold),
-            "ITALICS" => Some(Italics),
-            "NEWLINE" => Some(Newline),
-            "SOUNDZ" => Some(Soundz),
-            "VIDZ" => Some(Vidz),
-            "LIST" => Some(List),
-            "ITEM" => Some(Item),
-            "LEMME" => Some(Lemme),
-            "SEE" => Some(See),
-            "I" => Some(I),
-            "HAZ" => Some(Haz),
-            "IT" => Some(It),
-            "IZ" => Some(Iz),
-            "MKAY" => Some(Mkay),
-            _ => None,
+    /// Finds the keyword closest to `word` by edit distance, if any is close enough.
+    /// Short words allow fewer edits, so e.g. "END" is not taken for "HEAD".
+    /// Messages suggest the whole annotation it is part of ("#I HAZ", not "#I").
+    fn suggest(word: &str) -> Option<Kw> {
+        let upper = word.to_ascii_uppercase();
+        let max_edits = (upper.len() / 3).max(1);
+        KEYWORDS
+            .iter()
+            .map(|k| (edit_distance(&upper, k.spelling), k))
+            .filter(|(d, k)| *d <= max_edits && *d < k.spelling.len().max(upper.len()))
+            .min_by_key(|(d, _)| *d)
+            .map(|(_, k)| k.kw)
+    }
+
+    /// Builds the message for a word after '#' that is not a valid annotation.
+    fn bad_annotation(word: &str, kw: Option<Kw>) -> String {
+        if let Some(kw) = kw {
+            return format!("'{}' cannot start an annotation; did you mean '{}'?", word, kw.annotation());
+        }
+        match Self::suggest(word) {
+            Some(kw) => format!("unknown annotation '#{}'; did you mean '{}'?", word, kw.annotation()),
+            None => format!("unknown annotation '#{}'", word),
+        }
+    }
+
+    /// Reads a KODE body verbatim, stopping before the `#OIC` that closes it.
+    /// Only an `#OIC` alone on its own line closes the block, so code can
+    /// contain any annotation, lolcode examples included.
+    fn read_raw(&mut self) -> &'a str {
+        let start = self.pos;
+        while !self.eof() {
+            if self.bump() != '\n' {
+                continue;
+            }
+            let line = self.src[self.pos..].split('\n').next().unwrap_or("");
+            if line.trim_matches([' ', '\t', '\r']).eq_ignore_ascii_case("#OIC") {
+                self.take_while(|c| c == ' ' || c == '\t');
+                break;
+            }
         }
+        &self.src[start..self.pos]
     }
 
-    /// Some keywords require the *next* word also be a keyword.
-    fn prev_kw_expects_keyword(prev: Option<Kw>) -> bool {
-        matches!(prev, Some(Kw::Maek) | Some(Kw::Gimmeh) | Some(Kw::Lemme) | Some(Kw::I) | Some(Kw::It))
+    /// Return the next token from the input, tagged with its span.
+    pub fn next_token(&mut self) -> Result<Token<'a>> {
+        let (start, line, col) = (self.pos, self.line, self.col + 1);
+        let kind = self.next_kind();
+        let span = Span::new(self.file.clone(), start, self.pos, line, col);
+        match kind {
+            Ok(kind) => Ok(Token::new(kind, span)),
+            Err(msg) => Err(LolError::Lexical { span, msg }),
+        }
     }
 
-    /// Return the next token from the input.
-    pub fn next_token(&mut self) -> Result<Token> {
+    /// Read the next token kind, leaving the position just past it.
+    /// On a lexical error the bad text is still consumed, so lexing can resume.
+    fn next_kind(&mut self) -> std::result::Result<TokenKind<'a>, String> {
+        if self.raw_next {
+            self.raw_next = false;
+            return Ok(TokenKind::Raw(self.read_raw()));
+        }
+
         if self.eof() {
-            return Ok(Token::Eof);
+            return Ok(TokenKind::Eof);
         }
 
         let c = self.peek();
@@ -135,53 +179,108 @@ impl CharLexer {
             self.bump();
             self.after_hash = true;
             self.prev_kw = None;
-            return Ok(Token::Hash);
+            return Ok(TokenKind::Hash);
+        }
+
+        // '\' escapes a character that would otherwise be special:
+        //   \#  a literal '#'     \\  a literal '\'     "\ "  a space that is never trimmed
+        // Any other backslash is ordinary text.
+        if c == '\\' && matches!(self.src.as_bytes().get(self.pos + 1), Some(b'#' | b'\\' | b' ')) {
+            self.bump();
+            let e = &self.src[self.pos..self.pos + 1];
+            self.bump();
+            self.after_hash = false;
+            self.prev_kw = None;
+            return Ok(TokenKind::Escaped(e));
         }
 
         // Whitespace comes through as Text, the parser will ignore empty text.
         if c.is_whitespace() {
             let t = self.take_while(|ch| ch.is_whitespace());
-            return Ok(Token::Text(t));
+            return Ok(TokenKind::Text(t));
         }
 
         // Letters/numbers/underscore form a word.
         if Self::is_word_char(c) {
             let word = self.take_while(Self::is_word_char);
-            let upper = word.to_ascii_uppercase();
 
-            let keyword_ok = self.after_hash || Self::prev_kw_expects_keyword(self.prev_kw);
+            // Some keywords require the *next* word also be a keyword (e.g. MAEK HEAD).
+            let keyword_ok = self.after_hash || self.prev_kw.is_some_and(Kw::next_is_keyword);
+            let kw = if keyword_ok { lookup(word) } else { None };
 
-            if keyword_ok {
-                if let Some(kw) = Self::map_kw(&upper) {
-                    self.after_hash = false;
-                    self.prev_kw = Some(kw);
-                    return Ok(Token::Kw(kw));
-                }
+            // A word right after '#' must be a keyword that can start an annotation.
+            if self.after_hash && !kw.is_some_and(Kw::starts_annotation) {
+                self.after_hash = false;
+                self.prev_kw = None;
+                return Err(Self::bad_annotation(word, kw));
+            }
+
+            if let Some(kw) = kw {
+                self.after_hash = false;
+                self.prev_kw = Some(kw);
+                self.raw_next = kw == Kw::Kode;
+                return Ok(TokenKind::Kw(kw));
             }
 
             // Otherwise it's just a normal word.
             self.after_hash = false;
             self.prev_kw = None;
-            return Ok(Token::Word(word));
+            return Ok(TokenKind::Word(word));
         }
 
         // Punctuation allowed in text.
         if Self::is_text_punct(c) {
             let t = self.take_while(Self::is_text_punct);
-            return Ok(Token::Text(t));
+            return Ok(TokenKind::Text(t));
         }
 
         // Anything else is treated as a single text character.
-        let ch = self.bump();
-        Ok(Token::Text(ch.to_string()))
+        let start = self.pos;
+        self.bump();
+        Ok(TokenKind::Text(&self.src[start..self.pos]))
+    }
+}
+
+/// Yields every token up to (not including) `Eof`.
+/// After a lexical error the iterator keeps going, like the parser does.
+impl<'a> Iterator for CharLexer<'a> {
+    type Item = Result<Token<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.next_token() {
+            Ok(t) if t.kind == TokenKind::Eof => {
+                self.done = true;
+                None
+            }
+            other => Some(other),
+        }
+    }
+}
+
+/// Levenshtein distance between two ASCII-uppercased words.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diag = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let next = (diag + usize::from(ca != *cb)).min(row[j] + 1).min(row[j + 1] + 1);
+            diag = row[j + 1];
+            row[j + 1] = next;
+        }
     }
+    row[b.len()]
 }
 
 // Small required trait implementation (not used in actual parsing).
-impl LexicalAnalyzer for CharLexer {
+impl LexicalAnalyzer for CharLexer<'_> {
     fn get_char(&mut self) -> char { self.bump() }
     fn add_char(&mut self, _c: char) { }
     fn lookup(&self, s: &str) -> bool {
-        Self::map_kw(&s.to_ascii_uppercase()).is_some()
+        lookup(s).is_some()
     }
 }
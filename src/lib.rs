@@ -0,0 +1,86 @@
+// lib.rs
+// This file is the public face of the compiler, so other tools can use it
+// without running the lolmarkdownn binary.
+// `compile` runs the whole pipeline; `parse`, `analyze` and `generate` run
+// one stage each, and the stage types are exported for finer control:
+// 1) Lexer + parser   → AST        (lexer.rs, parser.rs)
+// 2) Semantic checks  → checked AST (semantic.rs)
+// 3) HTML generation  → String      (htmlgen.rs)
+// dump.rs prints the tokens or the AST, for debugging.
+
+pub mod lexer;
+pub mod parser;
+pub mod semantic;
+pub mod htmlgen;
+pub mod error;
+pub mod token;
+pub mod ast;
+pub mod span;
+pub mod diagnostic;
+pub mod dump;
+
+pub use ast::{Node, NodeKind};
+pub use diagnostic::{Diagnostic, DiagnosticKind, Severity};
+pub use error::LolError;
+pub use htmlgen::{HtmlFormat, HtmlGen};
+pub use parser::{Parser, SyntaxAnalyzer};
+pub use semantic::Analyzer;
+
+/// Every problem found in one run, in source order.
+pub type Diagnostics = Vec<Diagnostic>;
+
+/// Settings for `compile`.
+/// - file: the name recorded in spans and shown in diagnostics
+/// - format: which flavor of HTML to produce
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub file: String,
+    pub format: HtmlFormat,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self { file: "<input>".to_string(), format: HtmlFormat::default() }
+    }
+}
+
+/// The result of a successful compile: the page, plus any warnings.
+#[derive(Debug, Clone)]
+pub struct Output {
+    pub html: String,
+    pub warnings: Diagnostics,
+}
+
+/// Runs the lexer, parser, semantic analyzer and HTML generator over `source`.
+/// On failure, returns every problem found rather than just the first.
+pub fn compile(source: &str, options: Options) -> Result<Output, Diagnostics> {
+    let ast = parse(source, &options.file)?;
+    let (checked, warnings) = analyze(&ast)?;
+    let html = generate(&checked, options.format);
+    Ok(Output { html, warnings })
+}
+
+/// Stage 1: lexes and parses `source` into an AST.
+/// The parser recovers after each syntax error, so all of them are returned.
+pub fn parse(source: &str, file: &str) -> Result<Vec<Node>, Diagnostics> {
+    let single = |e: LolError| vec![Diagnostic::from(&e)];
+    let mut parser = Parser::new(source, file).map_err(single)?;
+    parser.parse_lolcode().map_err(single)?;
+    if !parser.diagnostics.is_empty() {
+        return Err(parser.diagnostics);
+    }
+    Ok(parser.ast)
+}
+
+/// Stage 2: checks variables, headings, tables, ... and resolves variable uses
+/// and the table of contents. Returns the checked AST and any warnings.
+pub fn analyze(ast: &[Node]) -> Result<(Vec<Node>, Diagnostics), Diagnostics> {
+    let mut analyzer = Analyzer::new(ast);
+    let checked = analyzer.check().map_err(|e| vec![Diagnostic::from(&e)])?;
+    Ok((checked, analyzer.warnings))
+}
+
+/// Stage 3: writes a checked AST out as HTML.
+pub fn generate(ast: &[Node], format: HtmlFormat) -> String {
+    HtmlGen::new(format).generate(ast)
+}
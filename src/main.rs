@@ -1,32 +1,24 @@
 // main.rs
-// This file controls the overall compilation pipeline:
+// This file is the command-line front end of the compiler:
+// 0) Parse the command line (see cli.rs)
 // 1) Read the .lol input file
-// 2) Run the lexer + parser to build an AST
-// 3) Run semantic checks (optional for this phase but included)
-// 4) Convert the AST to HTML
-// 5) Write the HTML to disk and optionally open in browser
+// 2) Compile it with the library (see lib.rs)
+// 3) Write the HTML to disk (or stdout) and optionally open in browser
 
 use std::fs;
-use std::path::PathBuf;
-use std::process::Command;
-
-mod lexer;
-mod parser;
-mod semantic;
-mod htmlgen;
-mod error;
-mod token;
-mod ast;
-
-use parser::{Parser, SyntaxAnalyzer};
-use semantic::Analyzer;
-use htmlgen::HtmlGen;
-use error::Result;
-
-/// Opens the generated HTML file in a browser (Windows/Mac support).
-fn open_in_browser(out_path: &PathBuf) {
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+
+mod cli;
+
+use lolmarkdownn::{Diagnostic, Output, Parser, SyntaxAnalyzer};
+use cli::{Command, Dump, Options};
+
+/// Opens the generated HTML file in a browser.
+/// `browser` overrides the platform default (a command, optionally with arguments).
+fn open_in_browser(out_path: &Path, browser: Option<&str>) -> std::io::Result<()> {
     // Convert path to an absolute path
-    let abs = fs::canonicalize(out_path).unwrap_or(out_path.clone());
+    let abs = fs::canonicalize(out_path).unwrap_or(out_path.to_path_buf());
     let mut s = abs.to_string_lossy().to_string();
 
     // Remove Windows "\\?\" prefix if present
@@ -35,56 +27,172 @@ fn open_in_browser(out_path: &PathBuf) {
     }
 
     // Convert path to a `file:///` URL (browsers require this format)
-    let file_url = format!("file:///{}", s.replace('\\', "/"));
+    let s = s.replace('\\', "/");
+    let file_url = format!("file:///{}", s.trim_start_matches('/'));
+
+    // An explicit --browser command wins over the platform default.
+    if let Some(cmd) = browser {
+        return spawn_command(cmd, &file_url);
+    }
 
     // Launch default browser depending on OS
     #[cfg(target_os = "windows")]
     {
-        let _ = Command::new("cmd").args(["/C", "start", "", &file_url]).spawn();
+        std::process::Command::new("cmd").args(["/C", "start", "", &file_url]).spawn()?;
     }
     #[cfg(target_os = "macos")]
     {
-        let _ = Command::new("open")
+        std::process::Command::new("open")
             .args(["-a", "Google Chrome", &file_url])
             .spawn()
-            .or_else(|_| Command::new("open").arg(&file_url).spawn());
+            .or_else(|_| std::process::Command::new("open").arg(&file_url).spawn())?;
+    }
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    {
+        // Linux and other Unixes: honor $BROWSER (a colon-separated list), else xdg-open.
+        let from_env = std::env::var("BROWSER").ok();
+        let cmd = from_env
+            .as_deref()
+            .and_then(|list| list.split(':').find(|c| !c.trim().is_empty()))
+            .unwrap_or("xdg-open");
+        spawn_command(cmd, &file_url)?;
     }
+    Ok(())
 }
 
-fn main() -> Result<()> {
-    // Get input file path from command line.
-    // Example: cargo run -- src/test.lol
-    let input = std::env::args()
-        .nth(1)
-        .expect("Usage: lolmarkdownn <file.lol>");
+/// Runs a browser command line such as "firefox --new-window" with `url` appended.
+fn spawn_command(cmd: &str, url: &str) -> std::io::Result<()> {
+    let mut parts = cmd.split_whitespace();
+    let program = parts.next().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "empty browser command")
+    })?;
+    std::process::Command::new(program).args(parts).arg(url).spawn()?;
+    Ok(())
+}
 
-    // Read the entire .lol program as text
-    let source = fs::read_to_string(&input)
-        .expect("Failed to read input file");
+/// Color is used only when stderr is a terminal and NO_COLOR is not set.
+fn use_color() -> bool {
+    std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+}
 
-    // 1) LEX + PARSE → produces AST
-    let mut parser = Parser::new(&source)?;
-    parser.parse_lolcode()?;      // fills parser.ast
+/// Prints `diagnostics` against the source and returns the exit code for them
+/// (0 when they are all warnings).
+fn report(diagnostics: &[Diagnostic], source: &str, input: &str) -> i32 {
+    let color = use_color();
+    // Show each problem against the source instead of a Debug dump.
+    for d in diagnostics {
+        eprintln!("{}", d.render(source, color));
+    }
+    let errors: Vec<&Diagnostic> = diagnostics.iter().filter(|d| d.is_error()).collect();
+    if errors.is_empty() {
+        return 0;
+    }
+    eprintln!("{} error(s) in {}", errors.len(), input);
+    cli::exit_code(errors.iter().map(|d| d.kind))
+}
 
-    // 2) SEMANTIC ANALYSIS → validate AST (e.g., variable checks)
-    let mut analyzer = Analyzer::new(&parser.ast);
-    let checked_ast = analyzer.check()?; // returns validated AST
+/// Prints the tokens or the AST of `source` instead of compiling it.
+fn run_dump(dump: Dump, source: &str, input: &str) -> i32 {
+    if dump == Dump::Tokens {
+        // The tokens are printed even after a lexical error, to show where it happened.
+        let (tokens, errors) = lolmarkdownn::dump::tokens(source, input);
+        print!("{}", tokens);
+        return report(&errors, source, input);
+    }
+
+    // The tree is printed even when parsing failed, with Error placeholders
+    // where the parser recovered, to show what it made of the document.
+    let mut parser = match Parser::new(source, input) {
+        Ok(p) => p,
+        Err(e) => return report(&[Diagnostic::from(&e)], source, input),
+    };
+    if let Err(e) = parser.parse_lolcode() {
+        parser.diagnostics.push(Diagnostic::from(&e));
+    }
+    match dump {
+        Dump::AstJson => print!("{}", lolmarkdownn::dump::json(&parser.ast)),
+        _ => print!("{}", lolmarkdownn::dump::tree(&parser.ast)),
+    }
+    report(&parser.diagnostics, source, input)
+}
+
+/// Compiles one file as described by `opts`, returning the process exit code.
+fn run(opts: &Options) -> i32 {
+    let input = opts.input.display().to_string();
+
+    // Read the entire .lol program as text
+    let source = match fs::read_to_string(&opts.input) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("error: cannot read {}: {}", input, e);
+            return cli::EXIT_IO;
+        }
+    };
+
+    if let Some(dump) = opts.dump {
+        return run_dump(dump, &source, &input);
+    }
 
-    // 3) HTML GENERATION → convert AST → HTML string
-    let mut html_gen = HtmlGen::new();
-    let html = html_gen.generate(&checked_ast);
+    let options = lolmarkdownn::Options { file: input.clone(), format: opts.format };
+    let html = match lolmarkdownn::compile(&source, options) {
+        Ok(Output { html, warnings }) => {
+            report(&warnings, &source, &input);
+            html
+        }
+        Err(diagnostics) => return report(&diagnostics, &source, &input),
+    };
+
+    if opts.stdout {
+        print!("{}", html);
+        return 0;
+    }
 
-    // Create output path by changing .lol → .html
-    let mut out_path = PathBuf::from(&input);
-    out_path.set_extension("html");
+    // Default output path: change .lol → .html
+    let out_path = opts.output.clone().unwrap_or_else(|| {
+        let mut p = PathBuf::from(&opts.input);
+        p.set_extension("html");
+        p
+    });
 
     // Write generated HTML to disk
-    fs::write(&out_path, html).expect("write html");
+    if let Err(e) = fs::write(&out_path, html) {
+        eprintln!("error: cannot write {}: {}", out_path.display(), e);
+        return cli::EXIT_IO;
+    }
 
     println!("✅ Generated: {}", out_path.display());
 
-    // Automatically open the HTML file (optional)
-    open_in_browser(&out_path);
+    // Open the HTML file only when asked, so headless runs never launch anything
+    if opts.open && let Err(e) = open_in_browser(&out_path, opts.browser.as_deref()) {
+        eprintln!("warning: could not open a browser: {}", e);
+    }
+    0
+}
 
-    Ok(())
+fn main() {
+    // Example: cargo run -- test/Test2.lol -o out.html
+    let code = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(Command::Compile(opts)) => run(&opts),
+        Ok(Command::Help) => {
+            println!("{}", cli::USAGE);
+            0
+        }
+        Ok(Command::Version) => {
+            println!("lolmarkdownn {}", env!("CARGO_PKG_VERSION"));
+            0
+        }
+        Ok(Command::Keywords) => {
+            print!("{}", lolmarkdownn::token::keyword_reference());
+            0
+        }
+        Ok(Command::Grammar) => {
+            print!("{}", lolmarkdownn::token::grammar());
+            0
+        }
+        Err(msg) => {
+            eprintln!("error: {}\nRun 'lolmarkdownn --help' for usage.", msg);
+            cli::EXIT_USAGE
+        }
+    };
+    std::process::exit(code);
 }
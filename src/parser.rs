@@ -1,10 +1,15 @@
-use crate::ast::Node;
+use std::collections::VecDeque;
+
+use crate::ast::{Node, NodeKind, NumberStyle};
+use crate::diagnostic::Diagnostic;
 use crate::error::{LolError, Result};
 use crate::lexer::CharLexer;
-use crate::token::{Kw, Token};
+use crate::span::Span;
+use crate::token::{one_of, Kw, Token, TokenKind};
 
 /// Defines the parsing functions used to read LOL code and build an AST.
 /// Each method handles one grammar rule.
+#[allow(dead_code)] // the parse_inner_* rules are part of the spec'd interface
 pub trait SyntaxAnalyzer {
     fn parse_lolcode(&mut self) -> Result<()>;
     fn parse_head(&mut self) -> Result<()>;
@@ -12,6 +17,9 @@ pub trait SyntaxAnalyzer {
     fn parse_comment(&mut self) -> Result<()>;
     fn parse_body(&mut self) -> Result<()>;
     fn parse_paragraph(&mut self) -> Result<()>;
+    fn parse_heading(&mut self) -> Result<()>;
+    fn parse_section(&mut self) -> Result<()>;
+    fn parse_toc(&mut self) -> Result<()>;
     fn parse_inner_paragraph(&mut self) -> Result<()>;
     fn parse_inner_text(&mut self) -> Result<()>;
     fn parse_variable_define(&mut self) -> Result<()>;
@@ -19,76 +27,280 @@ pub trait SyntaxAnalyzer {
     fn parse_bold(&mut self) -> Result<()>;
     fn parse_italics(&mut self) -> Result<()>;
     fn parse_list(&mut self) -> Result<()>;
+    fn parse_numbered_list(&mut self) -> Result<()>;
     fn parse_list_items(&mut self) -> Result<()>;
     fn parse_inner_list(&mut self) -> Result<()>;
+    fn parse_table(&mut self) -> Result<()>;
+    fn parse_code_block(&mut self) -> Result<()>;
+    fn parse_table_row(&mut self) -> Result<()>;
+    fn parse_table_cell(&mut self, header: bool) -> Result<()>;
     fn parse_audio(&mut self) -> Result<()>;
     fn parse_video(&mut self) -> Result<()>;
+    fn parse_image(&mut self) -> Result<()>;
+    fn parse_link(&mut self) -> Result<()>;
     fn parse_newline(&mut self) -> Result<()>;
     fn parse_text(&mut self) -> Result<()>;
 }
 
+// What each context accepts after '#' (or after its MAEK / GIMMEH), for error messages.
+const FORMATTING: &[&[Kw]] = &[&[Kw::Bold], &[Kw::Italics], &[Kw::Newline], &[Kw::Linkz]];
+const ITEM_INLINE: &[&[Kw]] =
+    &[&[Kw::Bold], &[Kw::Italics], &[Kw::Newline], &[Kw::Linkz], &[Kw::Soundz], &[Kw::Vidz], &[Kw::Pikchur]];
+const PARAGRAPH_INLINE: &[&[Kw]] =
+    &[&[Kw::Bold], &[Kw::Italics], &[Kw::Newline], &[Kw::Soundz], &[Kw::Vidz], &[Kw::Pikchur], &[Kw::Linkz]];
+const BODY_ELEMENTS: &[&[Kw]] = &[
+    &[Kw::Bold], &[Kw::Italics], &[Kw::Newline], &[Kw::Soundz], &[Kw::Vidz],
+    &[Kw::Pikchur], &[Kw::Linkz], &[Kw::Item], &[Kw::Title], &[Kw::Toc],
+];
+const LISTS: &[&[Kw]] = &[&[Kw::List], &[Kw::Numbrd, Kw::List]];
+const ROOT_BLOCKS: &[&[Kw]] = &[
+    &[Kw::Head], &[Kw::Paragraf], &[Kw::Hedr], &[Kw::Secshun],
+    &[Kw::List], &[Kw::Numbrd, Kw::List], &[Kw::Tabl], &[Kw::Kode],
+];
+const SECTION_BLOCKS: &[&[Kw]] = &[
+    &[Kw::Paragraf], &[Kw::Hedr], &[Kw::Secshun], &[Kw::List], &[Kw::Numbrd, Kw::List], &[Kw::Tabl], &[Kw::Kode],
+];
+const HEAD_CONTENT: &[&[Kw]] = &[&[Kw::Gimmeh, Kw::Title], &[Kw::OBTW], &[Kw::OIC]];
+const PARAGRAPH_CONTENT: &[&[Kw]] =
+    &[&[Kw::Gimmeh], &[Kw::Lemme], &[Kw::I], &[Kw::OBTW], &[Kw::OIC], &[Kw::Maek, Kw::List]];
+const SECTION_CONTENT: &[&[Kw]] = &[&[Kw::Maek], &[Kw::Gimmeh], &[Kw::Lemme], &[Kw::I], &[Kw::OBTW], &[Kw::OIC]];
+const HEADING_CONTENT: &[&[Kw]] = &[&[Kw::Gimmeh], &[Kw::Lemme], &[Kw::OBTW], &[Kw::OIC]];
+const TABLE_CONTENT: &[&[Kw]] = &[&[Kw::Maek, Kw::Row], &[Kw::OBTW], &[Kw::OIC]];
+const ROW_CONTENT: &[&[Kw]] = &[&[Kw::Gimmeh, Kw::Cell], &[Kw::OBTW], &[Kw::OIC]];
+const CELLS: &[&[Kw]] = &[&[Kw::Cell], &[Kw::Hedr, Kw::Cell]];
+const LIST_CONTENT: &[&[Kw]] = &[&[Kw::Gimmeh, Kw::Item], &[Kw::OBTW], &[Kw::OIC]];
+
+/// Which kind of element `parse_inline_until_mkay` is reading.
+/// - Formatting: BOLD / ITALICS / a LINKZ label
+/// - Item: a list ITEM, which also allows media and nested lists
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Inline {
+    Formatting,
+    Item,
+}
+
+/// What a block loop should do after handling one element.
+enum Step {
+    More,
+    Done,
+}
+
 /// The parser holds:
 /// - current lexer
-/// - current lookahead token
+/// - current lookahead token, plus tokens peeked past it during recovery
+/// - the spans of the last consumed token and the last consumed `#`,
+///   used to give every node the span of its whole annotation
+/// - whether the tokens consumed since the last `#` were only whitespace
 /// - the AST being constructed
 /// - a stack to support nested structures like PARAGRAF and LIST
+/// - the name and opening span of each block on that stack, for error notes
+/// - every error found so far; parsing continues after each one
 pub struct Parser<'a> {
-    lexer: CharLexer,
-    look: Token,
+    lexer: CharLexer<'a>,
+    look: Token<'a>,
+    ahead: VecDeque<Token<'a>>,
+    prev: Span,
+    last_hash: Span,
+    after_hash: bool,
     pub ast: Vec<Node>,
     stack: Vec<Vec<Node>>,
+    opened: Vec<(Span, &'static [Kw])>,
+    pub diagnostics: Vec<Diagnostic>,
     _src: &'a str,
 }
 
 impl<'a> Parser<'a> {
     /// Creates a new parser and reads the first token.
-    pub fn new(input: &'a str) -> Result<Self> {
-        let mut lx = CharLexer::new(input);
+    /// `file` is the name recorded in every span.
+    pub fn new(input: &'a str, file: &str) -> Result<Self> {
+        let mut lx = CharLexer::new(input, file);
         let first = lx.next_token()?;
         Ok(Self {
             lexer: lx,
+            prev: first.span.clone(),
+            last_hash: first.span.clone(),
             look: first,
+            ahead: VecDeque::new(),
+            after_hash: false,
             ast: vec![],
             stack: vec![],
+            opened: vec![],
+            diagnostics: vec![],
             _src: input,
         })
     }
 
     /// Moves to the next token.
     fn advance(&mut self) -> Result<()> {
-        self.look = self.lexer.next_token()?;
+        let next = match self.ahead.pop_front() {
+            Some(t) => t,
+            None => self.lexer.next_token()?,
+        };
+        let done = std::mem::replace(&mut self.look, next);
+        self.after_hash = match &done.kind {
+            TokenKind::Hash => true,
+            TokenKind::Text(t) => self.after_hash && t.trim().is_empty(),
+            _ => false,
+        };
+        if done.kind == TokenKind::Hash {
+            self.last_hash = done.span.clone();
+        }
+        self.prev = done.span;
         Ok(())
     }
 
-    /// Ensures the current token is a specific keyword.
-    fn expect_kw(&mut self, kw: Kw) -> Result<()> {
-        if let Token::Kw(k) = &self.look {
-            if *k == kw {
-                self.advance()?;
-                return Ok(());
+    /// Returns the keyword that follows the current token (usually a `#`),
+    /// skipping whitespace, without consuming anything.
+    fn peek_kw(&mut self) -> Result<Option<Kw>> {
+        let mut i = 0;
+        loop {
+            if i == self.ahead.len() {
+                let t = self.lexer.next_token()?;
+                self.ahead.push_back(t);
+            }
+            match &self.ahead[i].kind {
+                TokenKind::Text(t) if t.trim().is_empty() => i += 1,
+                TokenKind::Kw(k) => return Ok(Some(*k)),
+                _ => return Ok(None),
             }
         }
-        Err(LolError::Syntax {
-            expected: format!("{:?}", kw),
+    }
+
+    /// Steps back onto the `#` that introduced the current keyword.
+    fn unread_hash(&mut self) {
+        let hash = Token::new(TokenKind::Hash, self.last_hash.clone());
+        let kw = std::mem::replace(&mut self.look, hash);
+        self.ahead.push_front(kw);
+        self.after_hash = false;
+    }
+
+    /// Records an error and keeps going.
+    fn report(&mut self, e: LolError) {
+        self.diagnostics.push(Diagnostic::from(&e));
+    }
+
+    /// Advances, recording (rather than returning) a lexical error.
+    fn skip(&mut self) {
+        if let Err(e) = self.advance() {
+            self.report(e);
+        }
+    }
+
+    /// Builds a syntax error pointing at the current token.
+    /// The innermost open block is attached so the error can say where it began.
+    fn error(&self, expected: &str) -> LolError {
+        LolError::Syntax {
+            span: self.look.span.clone(),
+            expected: expected.into(),
             found: self.look.as_lexeme(),
-        })
+            opened: self.opened.last().map(|(span, what)| Box::new((span.clone(), format!("#{}", one_of(&[what]))))),
+        }
+    }
+
+    /// Starts collecting children for a nested block such as PARAGRAF.
+    /// `what` is the keywords that open it, e.g. `[Maek, Paragraf]` for "#MAEK PARAGRAF".
+    fn open_block(&mut self, what: &'static [Kw], start: &Span) {
+        self.stack.push(vec![]);
+        self.opened.push((start.to(&self.prev), what));
+    }
+
+    /// Finishes the innermost block and returns its children.
+    fn close_block(&mut self) -> Vec<Node> {
+        self.opened.pop();
+        self.stack.pop().unwrap_or_default()
+    }
+
+    /// Runs `step` until the block is closed.
+    /// When an element fails, the error is recorded, a `NodeKind::Error`
+    /// placeholder takes its place, and we resynchronize and carry on.
+    fn parse_block(&mut self, step: fn(&mut Self) -> Result<Step>) {
+        loop {
+            if let Err(e) = self.skip_ws() {
+                self.report(e);
+                continue;
+            }
+            let start = self.look.span.clone();
+            let (frames, opened) = (self.stack.len(), self.opened.len());
+            match step(self) {
+                Ok(Step::More) => {}
+                Ok(Step::Done) => break,
+                Err(e) => {
+                    self.report(e);
+                    self.stack.truncate(frames);
+                    self.opened.truncate(opened);
+                    self.push_node(NodeKind::Error, &start);
+                    self.synchronize();
+                }
+            }
+        }
+    }
+
+    /// Panic-mode recovery: skips tokens until the next `#MKAY` (consumed,
+    /// ending the broken element), or stops before the next `#OIC` of an
+    /// enclosing block, `#MAEK` or `#KTHXBYE` so the caller can handle it.
+    /// Returns true if it consumed a `#MKAY`.
+    fn synchronize(&mut self) -> bool {
+        // If the error was raised on the keyword right after a '#',
+        // that keyword may itself be where we should resume.
+        if self.after_hash && !matches!(self.look.kind, TokenKind::Hash | TokenKind::Eof) {
+            self.unread_hash();
+        }
+        loop {
+            match self.look.kind {
+                TokenKind::Eof => return false,
+                TokenKind::Hash => match self.peek_kw() {
+                    Ok(Some(Kw::Mkay)) => {
+                        while !matches!(self.look.kind, TokenKind::Kw(Kw::Mkay)) {
+                            self.skip();
+                        }
+                        self.skip();
+                        return true;
+                    }
+                    Ok(Some(Kw::OIC)) if self.stack.len() > 1 => return false,
+                    Ok(Some(Kw::Maek | Kw::Kthxbye)) => return false,
+                    Ok(_) => {}
+                    Err(e) => self.report(e),
+                },
+                _ => {}
+            }
+            self.skip();
+        }
+    }
+
+    /// Reports a block that ends without its `#OIC` and closes it.
+    /// Leaves whatever ended it (`#MAEK`, `#KTHXBYE`, EOF) for the enclosing block.
+    fn unclosed_block(&mut self) -> Step {
+        let e = self.error(&Kw::OIC.annotation());
+        self.report(e);
+        if self.look.kind != TokenKind::Eof {
+            self.unread_hash();
+        }
+        Step::Done
+    }
+
+    /// Ensures the current token is a specific keyword.
+    fn expect_kw(&mut self, kw: Kw) -> Result<()> {
+        if let TokenKind::Kw(k) = &self.look.kind && *k == kw {
+            self.advance()?;
+            return Ok(());
+        }
+        Err(self.error(kw.as_str()))
     }
 
     /// Ensures the current token is a '#'.
     fn expect_hash(&mut self) -> Result<()> {
-        if matches!(self.look, Token::Hash) {
+        if matches!(self.look.kind, TokenKind::Hash) {
             self.advance()?;
             Ok(())
         } else {
-            Err(LolError::Syntax {
-                expected: "#".into(),
-                found: self.look.as_lexeme(),
-            })
+            Err(self.error("#"))
         }
     }
 
     /// Adds a node either to the current nested block or to the root AST.
-    fn push_node(&mut self, n: Node) {
+    /// Its span runs from `start` to the end of the last consumed token.
+    fn push_node(&mut self, kind: NodeKind, start: &Span) {
+        let n = Node::new(kind, start.to(&self.prev));
         if let Some(top) = self.stack.last_mut() {
             top.push(n);
         } else {
@@ -98,7 +310,7 @@ impl<'a> Parser<'a> {
 
     /// Skips whitespace-only text tokens.
     fn skip_ws(&mut self) -> Result<()> {
-        while let Token::Text(t) = &self.look {
+        while let TokenKind::Text(t) = &self.look.kind {
             if t.trim().is_empty() {
                 self.advance()?;
             } else {
@@ -108,192 +320,497 @@ impl<'a> Parser<'a> {
         Ok(())
     }
 
+    /// Reads a single variable name (an identifier word).
+    fn read_var_name(&mut self) -> Result<String> {
+        match &self.look.kind {
+            TokenKind::Word(w) => { let s = w.to_string(); self.advance()?; Ok(s) }
+            _ => Err(self.error("variable name"))
+        }
+    }
+
     /// Reads text until another control symbol (`#`) appears.
+    /// Surrounding whitespace is trimmed, except whitespace written as an escape.
     fn read_text_until_hash(&mut self) -> Result<String> {
         let mut out = String::new();
+        let (mut keep_from, mut keep_to) = (None, 0);
         loop {
-            match &self.look {
-                Token::Text(t) => { out.push_str(t); self.advance()?; }
-                Token::Word(w) => { out.push_str(w); self.advance()?; }
-                Token::Kw(_) | Token::Hash | Token::Eof => break,
+            match &self.look.kind {
+                TokenKind::Text(t) | TokenKind::Word(t) => {
+                    if !t.trim().is_empty() {
+                        keep_from.get_or_insert(out.len() + t.len() - t.trim_start().len());
+                        keep_to = out.len() + t.trim_end().len();
+                    }
+                    out.push_str(t);
+                    self.advance()?;
+                }
+                TokenKind::Escaped(e) => {
+                    keep_from.get_or_insert(out.len());
+                    out.push_str(e);
+                    keep_to = out.len();
+                    self.advance()?;
+                }
+                TokenKind::Kw(_) | TokenKind::Hash | TokenKind::Raw(_) | TokenKind::Eof => break,
             }
         }
-        Ok(out.trim().to_string())
+        Ok(keep_from.map_or(String::new(), |from| out[from..keep_to].to_string()))
     }
-}
-
-impl<'a> SyntaxAnalyzer for Parser<'a> {
-
-    /// Parses the whole LOL program.
-    /// Must start with #HAI and end with #KTHXBYE.
-    fn parse_lolcode(&mut self) -> Result<()> {
-        self.expect_hash()?;
-        self.expect_kw(Kw::Hai)?;
-
-        // Start root block
-        self.stack.push(vec![]);
-        self.skip_ws()?;
-
+    /// Reads the contents of an inline element such as BOLD up to its `#MKAY`.
+    /// Text, `#GIMMEH BOLD/ITALICS/NEWLINE` and `#LEMME SEE` may be nested inside;
+    /// list items also accept media and nested lists.
+    /// A broken `#GIMMEH` / `#LEMME` element inside is recovered from here, so
+    /// the `#MKAY` that closes this element is not mistaken for a stray one.
+    fn parse_inline_until_mkay(&mut self, what: &'static [Kw], start: &Span, ctx: Inline) -> Result<Vec<Node>> {
+        self.open_block(what, start);
+        let (frames, opened) = (self.stack.len(), self.opened.len());
         loop {
-            self.skip_ws()?;
-
-            match self.look {
-                Token::Hash => {
+            match &self.look.kind {
+                TokenKind::Text(_) | TokenKind::Word(_) | TokenKind::Escaped(_) => self.parse_text()?,
+                TokenKind::Hash => {
+                    let at = self.look.span.clone();
                     self.advance()?;
                     self.skip_ws()?;
-
-                    match &self.look {
-                        Token::Kw(Kw::Kthxbye) => { self.advance()?; break; }
-                        Token::Kw(Kw::OBTW) => self.parse_comment()?,
-                        Token::Kw(Kw::Maek) => {
+                    match &self.look.kind {
+                        TokenKind::Kw(Kw::Mkay) => { self.advance()?; break; }
+                        TokenKind::Kw(Kw::Gimmeh | Kw::Lemme) => {
+                            if let Err(e) = self.parse_inline_element(ctx) {
+                                self.report(e);
+                                self.stack.truncate(frames);
+                                self.opened.truncate(opened);
+                                self.push_node(NodeKind::Error, &at);
+                                // Stopped before an #OIC, #MAEK or #KTHXBYE: the error already
+                                // covers this element being cut short, so just end it.
+                                if !self.synchronize() {
+                                    break;
+                                }
+                            }
+                        }
+                        TokenKind::Kw(Kw::Maek) if ctx == Inline::Item => {
                             self.advance()?;
                             self.skip_ws()?;
-                            match &self.look {
-                                Token::Kw(Kw::Head)     => self.parse_head()?,
-                                Token::Kw(Kw::Paragraf) => self.parse_paragraph()?,
-                                Token::Kw(Kw::List)     => self.parse_list()?,
-                                _ => return Err(LolError::Syntax {
-                                    expected: "HEAD/PARAGRAF/LIST".into(),
-                                    found: self.look.as_lexeme(),
-                                })
+                            match &self.look.kind {
+                                TokenKind::Kw(Kw::List)   => self.parse_list()?,
+                                TokenKind::Kw(Kw::Numbrd) => self.parse_numbered_list()?,
+                                _ => return Err(self.error(&one_of(LISTS))),
                             }
                         }
-                        Token::Kw(Kw::Gimmeh) => self.parse_body()?,
-                        Token::Kw(Kw::Lemme) => self.parse_variable_use()?,
-                        Token::Kw(Kw::I)     => self.parse_variable_define()?,
-
-                        // If someone writes HEAD without MAEK first
-                        Token::Kw(Kw::Head) => {
-                            return Err(LolError::Syntax {
-                                expected: "Use #MAEK HEAD ... #OIC".into(),
-                                found: "HEAD".into(),
-                            });
-                        }
+                        _ => return Err(self.error(&Kw::Mkay.annotation()))
+                    }
+                }
+                _ => return Err(self.error(&Kw::Mkay.annotation())),
+            }
+        }
+        let mut kids = self.close_block();
+        trim_edges(&mut kids);
+        Ok(kids)
+    }
 
-                        _ => return Err(LolError::Syntax {
-                            expected: "valid top-level annotation".into(),
-                            found: self.look.as_lexeme(),
-                        })
+    /// One `#GIMMEH ...` or `#LEMME SEE` element nested in an inline element.
+    fn parse_inline_element(&mut self, ctx: Inline) -> Result<()> {
+        if self.look.kind == TokenKind::Kw(Kw::Lemme) {
+            return self.parse_variable_use();
+        }
+        self.advance()?;
+        self.skip_ws()?;
+        match (&self.look.kind, ctx) {
+            (TokenKind::Kw(Kw::Bold), _)    => self.parse_bold(),
+            (TokenKind::Kw(Kw::Italics), _) => self.parse_italics(),
+            (TokenKind::Kw(Kw::Newline), _) => self.parse_newline(),
+            (TokenKind::Kw(Kw::Linkz), _)   => self.parse_link(),
+            (TokenKind::Kw(Kw::Soundz), Inline::Item) => self.parse_audio(),
+            (TokenKind::Kw(Kw::Vidz), Inline::Item)   => self.parse_video(),
+            (TokenKind::Kw(Kw::Pikchur), Inline::Item) => self.parse_image(),
+            (_, Inline::Item) => Err(self.error(&one_of(ITEM_INLINE))),
+            (_, Inline::Formatting) => Err(self.error(&one_of(FORMATTING))),
+        }
+    }
+
+    /// One element at the top level of the program.
+    fn root_step(&mut self) -> Result<Step> {
+        match self.look.kind {
+            TokenKind::Hash => {
+                self.advance()?;
+                self.skip_ws()?;
+
+                match &self.look.kind {
+                    TokenKind::Kw(Kw::Kthxbye) => { self.advance()?; return Ok(Step::Done); }
+                    TokenKind::Kw(Kw::OBTW) => self.parse_comment()?,
+                    TokenKind::Kw(Kw::Maek) => {
+                        self.advance()?;
+                        self.skip_ws()?;
+                        match &self.look.kind {
+                            TokenKind::Kw(Kw::Head)     => self.parse_head()?,
+                            TokenKind::Kw(Kw::Paragraf) => self.parse_paragraph()?,
+                            TokenKind::Kw(Kw::Hedr)     => self.parse_heading()?,
+                            TokenKind::Kw(Kw::Secshun)  => self.parse_section()?,
+                            TokenKind::Kw(Kw::List)     => self.parse_list()?,
+                            TokenKind::Kw(Kw::Numbrd)   => self.parse_numbered_list()?,
+                            TokenKind::Kw(Kw::Tabl)     => self.parse_table()?,
+                            TokenKind::Kw(Kw::Kode)     => self.parse_code_block()?,
+                            _ => return Err(self.error(&one_of(ROOT_BLOCKS)))
+                        }
                     }
+                    TokenKind::Kw(Kw::Gimmeh) => self.parse_body()?,
+                    TokenKind::Kw(Kw::Lemme) => self.parse_variable_use()?,
+                    TokenKind::Kw(Kw::I)     => self.parse_variable_define()?,
+
+                    _ => return Err(self.error("valid top-level annotation"))
                 }
+            }
 
-                // Allow text at top-level (HTML paragraph-like behavior)
-                Token::Text(_) | Token::Word(_) => self.parse_text()?,
+            // Allow text at top-level (HTML paragraph-like behavior)
+            TokenKind::Text(_) | TokenKind::Word(_) | TokenKind::Escaped(_) => self.parse_text()?,
 
-                Token::Eof => return Err(LolError::Syntax {
-                    expected: "#KTHXBYE".into(),
-                    found: "<EOF>".into(),
-                }),
+            TokenKind::Eof => {
+                let e = self.error(&Kw::Kthxbye.annotation());
+                self.report(e);
+                return Ok(Step::Done);
+            }
 
-                _ => {}
+            _ => return Err(self.error("text or annotation")),
+        }
+        Ok(Step::More)
+    }
+
+    /// One element inside a HEAD block.
+    fn head_step(&mut self) -> Result<Step> {
+        match self.look.kind {
+            TokenKind::Hash => {
+                self.advance()?;
+                self.skip_ws()?;
+                match &self.look.kind {
+                    TokenKind::Kw(Kw::Gimmeh) => { self.advance()?; self.skip_ws()?; self.parse_title()?; }
+                    TokenKind::Kw(Kw::OBTW) => self.parse_comment()?,
+                    TokenKind::Kw(Kw::OIC) => { self.advance()?; return Ok(Step::Done); }
+                    TokenKind::Kw(Kw::Maek | Kw::Kthxbye) => return Ok(self.unclosed_block()),
+                    _ => return Err(self.error(&one_of(HEAD_CONTENT)))
+                }
             }
+            TokenKind::Eof => return Ok(self.unclosed_block()),
+            _ => { self.advance()?; }
         }
+        Ok(Step::More)
+    }
 
-        // Finalize AST
-        if let Some(children) = self.stack.pop() {
-            self.ast = children;
+    /// One element inside a PARAGRAF block.
+    fn paragraph_step(&mut self) -> Result<Step> {
+        match self.look.kind {
+            TokenKind::Hash => {
+                self.advance()?;
+                self.skip_ws()?;
+                match &self.look.kind {
+                    TokenKind::Kw(Kw::Gimmeh) => {
+                        self.advance()?; self.skip_ws()?;
+                        match &self.look.kind {
+                            TokenKind::Kw(Kw::Bold)    => self.parse_bold()?,
+                            TokenKind::Kw(Kw::Italics) => self.parse_italics()?,
+                            TokenKind::Kw(Kw::Newline) => self.parse_newline()?,
+                            TokenKind::Kw(Kw::Soundz)  => self.parse_audio()?,
+                            TokenKind::Kw(Kw::Vidz)    => self.parse_video()?,
+                            TokenKind::Kw(Kw::Pikchur) => self.parse_image()?,
+                            TokenKind::Kw(Kw::Linkz)   => self.parse_link()?,
+                            _ => return Err(self.error(&one_of(PARAGRAPH_INLINE)))
+                        }
+                    }
+                    TokenKind::Kw(Kw::Lemme) => self.parse_variable_use()?,
+                    TokenKind::Kw(Kw::I)     => self.parse_variable_define()?,
+                    TokenKind::Kw(Kw::OBTW)  => self.parse_comment()?,
+                    TokenKind::Kw(Kw::OIC)   => { self.advance()?; return Ok(Step::Done); }
+                    TokenKind::Kw(Kw::Maek) => {
+                        // A LIST or NUMBRD LIST may sit inside a paragraph; any other block means
+                        // this paragraph was never closed.
+                        let next = self.peek_kw()?;
+                        if !matches!(next, Some(Kw::List | Kw::Numbrd)) {
+                            return Ok(self.unclosed_block());
+                        }
+                        self.advance()?;
+                        self.skip_ws()?;
+                        if next == Some(Kw::List) {
+                            self.parse_list()?;
+                        } else {
+                            self.parse_numbered_list()?;
+                        }
+                    }
+                    TokenKind::Kw(Kw::Kthxbye) => return Ok(self.unclosed_block()),
+                    _ => return Err(self.error(&one_of(PARAGRAPH_CONTENT)))
+                }
+            }
+            TokenKind::Text(_) | TokenKind::Word(_) | TokenKind::Escaped(_) => self.parse_text()?,
+            TokenKind::Eof => return Ok(self.unclosed_block()),
+            _ => return Err(self.error(&format!("content in {}", Kw::Paragraf.as_str()))),
         }
-        Ok(())
+        Ok(Step::More)
     }
 
-    /// Parses a HEAD block.
-    fn parse_head(&mut self) -> Result<()> {
-        self.expect_kw(Kw::Head)?;
-        self.stack.push(vec![]);
+    /// One element inside a SECSHUN block: the same blocks as the top level,
+    /// except HEAD.
+    fn section_step(&mut self) -> Result<Step> {
+        match self.look.kind {
+            TokenKind::Hash => {
+                self.advance()?;
+                self.skip_ws()?;
+                match &self.look.kind {
+                    TokenKind::Kw(Kw::OIC) => { self.advance()?; return Ok(Step::Done); }
+                    TokenKind::Kw(Kw::OBTW) => self.parse_comment()?,
+                    TokenKind::Kw(Kw::Maek) => {
+                        self.advance()?;
+                        self.skip_ws()?;
+                        match &self.look.kind {
+                            TokenKind::Kw(Kw::Paragraf) => self.parse_paragraph()?,
+                            TokenKind::Kw(Kw::Hedr)     => self.parse_heading()?,
+                            TokenKind::Kw(Kw::Secshun)  => self.parse_section()?,
+                            TokenKind::Kw(Kw::List)     => self.parse_list()?,
+                            TokenKind::Kw(Kw::Numbrd)   => self.parse_numbered_list()?,
+                            TokenKind::Kw(Kw::Tabl)     => self.parse_table()?,
+                            TokenKind::Kw(Kw::Kode)     => self.parse_code_block()?,
+                            TokenKind::Kw(Kw::Head)     => return Ok(self.unclosed_block()),
+                            _ => return Err(self.error(&one_of(SECTION_BLOCKS)))
+                        }
+                    }
+                    TokenKind::Kw(Kw::Gimmeh) => self.parse_body()?,
+                    TokenKind::Kw(Kw::Lemme) => self.parse_variable_use()?,
+                    TokenKind::Kw(Kw::I)     => self.parse_variable_define()?,
+                    TokenKind::Kw(Kw::Kthxbye) => return Ok(self.unclosed_block()),
+                    _ => return Err(self.error(&one_of(SECTION_CONTENT)))
+                }
+            }
+            TokenKind::Text(_) | TokenKind::Word(_) | TokenKind::Escaped(_) => self.parse_text()?,
+            TokenKind::Eof => return Ok(self.unclosed_block()),
+            _ => return Err(self.error(&format!("content in {}", Kw::Secshun.as_str()))),
+        }
+        Ok(Step::More)
+    }
 
-        loop {
-            self.skip_ws()?;
-            match self.look {
-                Token::Hash => {
-                    self.advance()?;
-                    self.skip_ws()?;
-                    match &self.look {
-                        Token::Kw(Kw::Gimmeh) => { self.advance()?; self.skip_ws()?; self.parse_title()?; }
-                        Token::Kw(Kw::OBTW) => self.parse_comment()?,
-                        Token::Kw(Kw::OIC) => { self.advance()?; break; }
-                        _ => return Err(LolError::Syntax {
-                            expected: "GIMMEH TITLE or OBTW or OIC".into(),
-                            found: self.look.as_lexeme(),
-                        })
+    /// One element inside a TABL block.
+    fn table_step(&mut self) -> Result<Step> {
+        match self.look.kind {
+            TokenKind::Hash => {
+                self.advance()?;
+                self.skip_ws()?;
+                match &self.look.kind {
+                    TokenKind::Kw(Kw::Maek) => {
+                        // Any block other than a ROW means this table was never closed.
+                        if self.peek_kw()? != Some(Kw::Row) {
+                            return Ok(self.unclosed_block());
+                        }
+                        self.advance()?; self.skip_ws()?; self.parse_table_row()?;
                     }
+                    TokenKind::Kw(Kw::OBTW) => self.parse_comment()?,
+                    TokenKind::Kw(Kw::OIC)  => { self.advance()?; return Ok(Step::Done); }
+                    TokenKind::Kw(Kw::Kthxbye) => return Ok(self.unclosed_block()),
+                    _ => return Err(self.error(&one_of(TABLE_CONTENT)))
+                }
+            }
+            TokenKind::Eof => return Ok(self.unclosed_block()),
+            _ => return Err(self.error(&format!("# in {}", Kw::Tabl.as_str()))),
+        }
+        Ok(Step::More)
+    }
+
+    /// One element inside a ROW block.
+    fn row_step(&mut self) -> Result<Step> {
+        match self.look.kind {
+            TokenKind::Hash => {
+                self.advance()?;
+                self.skip_ws()?;
+                match &self.look.kind {
+                    TokenKind::Kw(Kw::Gimmeh) => {
+                        self.advance()?; self.skip_ws()?;
+                        match &self.look.kind {
+                            TokenKind::Kw(Kw::Cell) => self.parse_table_cell(false)?,
+                            TokenKind::Kw(Kw::Hedr) => { self.advance()?; self.skip_ws()?; self.parse_table_cell(true)?; }
+                            _ => return Err(self.error(&one_of(CELLS)))
+                        }
+                    }
+                    TokenKind::Kw(Kw::OBTW) => self.parse_comment()?,
+                    TokenKind::Kw(Kw::OIC)  => { self.advance()?; return Ok(Step::Done); }
+                    TokenKind::Kw(Kw::Maek | Kw::Kthxbye) => return Ok(self.unclosed_block()),
+                    _ => return Err(self.error(&one_of(ROW_CONTENT)))
+                }
+            }
+            TokenKind::Eof => return Ok(self.unclosed_block()),
+            _ => return Err(self.error(&format!("# in {}", Kw::Row.as_str()))),
+        }
+        Ok(Step::More)
+    }
+
+    /// One element inside a HEDR block: text and inline formatting only.
+    fn heading_step(&mut self) -> Result<Step> {
+        match self.look.kind {
+            TokenKind::Hash => {
+                self.advance()?;
+                self.skip_ws()?;
+                match &self.look.kind {
+                    TokenKind::Kw(Kw::Gimmeh) => {
+                        self.advance()?; self.skip_ws()?;
+                        match &self.look.kind {
+                            TokenKind::Kw(Kw::Bold)    => self.parse_bold()?,
+                            TokenKind::Kw(Kw::Italics) => self.parse_italics()?,
+                            TokenKind::Kw(Kw::Newline) => self.parse_newline()?,
+                            TokenKind::Kw(Kw::Linkz)   => self.parse_link()?,
+                            _ => return Err(self.error(&one_of(FORMATTING)))
+                        }
+                    }
+                    TokenKind::Kw(Kw::Lemme) => self.parse_variable_use()?,
+                    TokenKind::Kw(Kw::OBTW)  => self.parse_comment()?,
+                    TokenKind::Kw(Kw::OIC)   => { self.advance()?; return Ok(Step::Done); }
+                    TokenKind::Kw(Kw::Maek | Kw::Kthxbye) => return Ok(self.unclosed_block()),
+                    _ => return Err(self.error(&one_of(HEADING_CONTENT)))
+                }
+            }
+            TokenKind::Text(_) | TokenKind::Word(_) | TokenKind::Escaped(_) => self.parse_text()?,
+            TokenKind::Eof => return Ok(self.unclosed_block()),
+            _ => return Err(self.error(&format!("content in {}", Kw::Hedr.as_str()))),
+        }
+        Ok(Step::More)
+    }
+
+    /// One element inside a LIST block.
+    fn list_step(&mut self) -> Result<Step> {
+        match self.look.kind {
+            TokenKind::Hash => {
+                self.advance()?;
+                self.skip_ws()?;
+                match &self.look.kind {
+                    TokenKind::Kw(Kw::Gimmeh) => { self.advance()?; self.skip_ws()?; self.parse_list_items()?; }
+                    TokenKind::Kw(Kw::OBTW)   => self.parse_comment()?,
+                    TokenKind::Kw(Kw::OIC)    => { self.advance()?; return Ok(Step::Done); }
+                    TokenKind::Kw(Kw::Maek | Kw::Kthxbye) => return Ok(self.unclosed_block()),
+                    _ => return Err(self.error(&one_of(LIST_CONTENT)))
                 }
-                Token::Eof => return Err(LolError::Syntax {
-                    expected: "#OIC".into(),
-                    found: "<EOF>".into(),
-                }),
-                _ => { self.advance()?; }
             }
+            TokenKind::Eof => return Ok(self.unclosed_block()),
+            _ => return Err(self.error(&format!("# in {}", Kw::List.as_str()))),
+        }
+        Ok(Step::More)
+    }
+}
+
+impl<'a> SyntaxAnalyzer for Parser<'a> {
+
+    /// Parses the whole LOL program.
+    /// Must start with #HAI and end with #KTHXBYE.
+    /// Errors are collected in `diagnostics`; the AST holds whatever could be parsed.
+    fn parse_lolcode(&mut self) -> Result<()> {
+        // A missing #HAI is reported, but we still parse the rest of the file.
+        if let Err(e) = self.expect_hash().and_then(|_| self.expect_kw(Kw::Hai)) {
+            self.report(e);
         }
 
-        let kids = self.stack.pop().unwrap();
-        self.push_node(Node::Head(kids));
+        // Start root block
+        self.stack.push(vec![]);
+        self.parse_block(Self::root_step);
+
+        // Finalize AST
+        self.ast = self.stack.pop().unwrap_or_default();
+        Ok(())
+    }
+
+    /// Parses a HEAD block.
+    fn parse_head(&mut self) -> Result<()> {
+        let start = self.last_hash.clone();
+        self.expect_kw(Kw::Head)?;
+        self.open_block(&[Kw::Maek, Kw::Head], &start);
+        self.parse_block(Self::head_step);
+
+        let kids = self.close_block();
+        self.push_node(NodeKind::Head(kids), &start);
         Ok(())
     }
 
     fn parse_title(&mut self) -> Result<()> {
+        let start = self.last_hash.clone();
         self.expect_kw(Kw::Title)?;
         let t = self.read_text_until_hash()?;
         self.expect_hash()?;
         self.expect_kw(Kw::Mkay)?;
-        self.push_node(Node::Title(t));
+        self.push_node(NodeKind::Title(t), &start);
         Ok(())
     }
 
     /// Reads OBTW ... TLDR comments.
     fn parse_comment(&mut self) -> Result<()> {
+        let start = self.last_hash.clone();
         self.expect_kw(Kw::OBTW)?;
+        self.opened.push((start.to(&self.prev), &[Kw::OBTW]));
         let mut text = String::new();
         loop {
-            match &self.look {
-                Token::Hash => { self.advance()?; self.skip_ws()?; self.expect_kw(Kw::TLDR)?; break; }
-                Token::Text(t) => { text.push_str(t); self.advance()?; }
-                Token::Word(w) => { text.push_str(w); self.advance()?; }
-                Token::Eof => return Err(LolError::Syntax { expected: "#TLDR".into(), found: "<EOF>".into() }),
+            match &self.look.kind {
+                TokenKind::Hash => { self.advance()?; self.skip_ws()?; self.expect_kw(Kw::TLDR)?; break; }
+                TokenKind::Text(t) => { text.push_str(t); self.advance()?; }
+                TokenKind::Word(w) => { text.push_str(w); self.advance()?; }
+                TokenKind::Escaped(e) => { text.push_str(e); self.advance()?; }
+                TokenKind::Eof => return Err(self.error(&Kw::TLDR.annotation())),
                 _ => { self.advance()?; }
             }
         }
-        self.push_node(Node::Comment(text.trim().to_string()));
+        self.opened.pop();
+        self.push_node(NodeKind::Comment(text.trim().to_string()), &start);
         Ok(())
     }
 
     /// Parses a PARAGRAF block.
     fn parse_paragraph(&mut self) -> Result<()> {
+        let start = self.last_hash.clone();
         self.expect_kw(Kw::Paragraf)?;
-        self.stack.push(vec![]);
+        self.open_block(&[Kw::Maek, Kw::Paragraf], &start);
+        self.parse_block(Self::paragraph_step);
 
-        loop {
-            self.skip_ws()?;
-            match self.look {
-                Token::Hash => {
-                    self.advance()?;
-                    self.skip_ws()?;
-                    match &self.look {
-                        Token::Kw(Kw::Gimmeh) => {
-                            self.advance()?; self.skip_ws()?;
-                            match &self.look {
-                                Token::Kw(Kw::Bold)    => self.parse_bold()?,
-                                Token::Kw(Kw::Italics) => self.parse_italics()?,
-                                Token::Kw(Kw::Newline) => self.parse_newline()?,
-                                Token::Kw(Kw::Soundz)  => self.parse_audio()?,
-                                Token::Kw(Kw::Vidz)    => self.parse_video()?,
-                                _ => return Err(LolError::Syntax { expected: "BOLD/ITALICS/NEWLINE/SOUNDZ/VIDZ".into(), found: self.look.as_lexeme() })
-                            }
-                        }
-                        Token::Kw(Kw::Lemme) => self.parse_variable_use()?,
-                        Token::Kw(Kw::I)     => self.parse_variable_define()?,
-                        Token::Kw(Kw::OBTW)  => self.parse_comment()?,
-                        Token::Kw(Kw::OIC)   => { self.advance()?; break; }
-                        _ => return Err(LolError::Syntax { expected: "GIMMEH/LEMME/I/OBTW/OIC".into(), found: self.look.as_lexeme() })
-                    }
-                }
-                Token::Text(_) | Token::Word(_) => self.parse_text()?,
-                Token::Eof => return Err(LolError::Syntax { expected: "#OIC".into(), found: "<EOF>".into() }),
-                _ => return Err(LolError::Syntax { expected: "content in PARAGRAF".into(), found: self.look.as_lexeme() }),
-            }
+        let inner = self.close_block();
+        self.push_node(NodeKind::Paragraph(inner), &start);
+        Ok(())
+    }
+
+    /// Heading:  MAEK HEDR level ... OIC
+    /// Any number is accepted here; the semantic pass checks the 1-6 range.
+    fn parse_heading(&mut self) -> Result<()> {
+        let start = self.last_hash.clone();
+        self.expect_kw(Kw::Hedr)?;
+        self.skip_ws()?;
+        let level = match &self.look.kind {
+            // Too many digits for a u32 is out of range all the same.
+            TokenKind::Word(w) if w.bytes().all(|b| b.is_ascii_digit()) => w.parse::<u32>().unwrap_or(u32::MAX),
+            _ => return Err(self.error("heading level 1-6")),
+        };
+        self.advance()?;
+
+        self.open_block(&[Kw::Maek, Kw::Hedr], &start);
+        self.parse_block(Self::heading_step);
+
+        let mut children = self.close_block();
+        trim_edges(&mut children);
+        self.push_node(NodeKind::Heading { level, id: String::new(), children }, &start);
+        Ok(())
+    }
+
+    /// Section:  MAEK SECSHUN title ... OIC
+    /// The title runs up to the first '#'. Top-level sections get an <h2> title,
+    /// leaving <h1> for the page heading; each nested section goes one level down.
+    fn parse_section(&mut self) -> Result<()> {
+        let start = self.last_hash.clone();
+        self.expect_kw(Kw::Secshun)?;
+        let title = self.read_text_until_hash()?;
+        if title.is_empty() {
+            return Err(self.error("section title"));
         }
+        let depth = self.opened.iter().filter(|(_, what)| *what == [Kw::Maek, Kw::Secshun]).count();
+        let level = (depth + 2).min(6) as u8;
+
+        self.open_block(&[Kw::Maek, Kw::Secshun], &start);
+        self.parse_block(Self::section_step);
 
-        let inner = self.stack.pop().unwrap();
-        self.push_node(Node::Paragraph(inner));
+        let children = self.close_block();
+        self.push_node(NodeKind::Section { title, level, id: String::new(), children }, &start);
+        Ok(())
+    }
+
+    /// Table of contents:  GIMMEH TOC #MKAY
+    fn parse_toc(&mut self) -> Result<()> {
+        let start = self.last_hash.clone();
+        self.expect_kw(Kw::Toc)?;
+        self.skip_ws()?;
+        self.expect_hash()?;
+        self.expect_kw(Kw::Mkay)?;
+        self.push_node(NodeKind::Toc, &start);
         Ok(())
     }
 
@@ -302,16 +819,18 @@ impl<'a> SyntaxAnalyzer for Parser<'a> {
 
     /// Variable definition:  I HAZ var IT IZ value #MKAY
     fn parse_variable_define(&mut self) -> Result<()> {
+        let start = self.last_hash.clone();
         self.expect_kw(Kw::I)?;
+        self.skip_ws()?;
         self.expect_kw(Kw::Haz)?;
+        self.skip_ws()?;
 
-        let name = match &self.look {
-            Token::Word(w) => { let s = w.clone(); self.advance()?; s }
-            Token::Text(t) => { let s = t.split_whitespace().next().unwrap_or("").to_string(); self.advance()?; s }
-            _ => return Err(LolError::Syntax { expected: "variable name".into(), found: self.look.as_lexeme() })
-        };
+        let name = self.read_var_name()?;
 
+        self.skip_ws()?;
+        self.expect_hash()?;
         self.expect_kw(Kw::It)?;
+        self.skip_ws()?;
         self.expect_kw(Kw::Iz)?;
 
         let value = self.read_text_until_hash()?;
@@ -319,24 +838,24 @@ impl<'a> SyntaxAnalyzer for Parser<'a> {
         self.expect_hash()?;
         self.expect_kw(Kw::Mkay)?;
 
-        self.push_node(Node::VarDef { name, value });
+        self.push_node(NodeKind::VarDef { name, value }, &start);
         Ok(())
     }
 
     /// Variable use:  LEMME SEE var #MKAY
     fn parse_variable_use(&mut self) -> Result<()> {
+        let start = self.last_hash.clone();
         self.expect_kw(Kw::Lemme)?;
+        self.skip_ws()?;
         self.expect_kw(Kw::See)?;
+        self.skip_ws()?;
 
-        let name = match &self.look {
-            Token::Word(w) => { let s = w.clone(); self.advance()?; s }
-            Token::Text(t) => { let s = t.split_whitespace().next().unwrap_or("").to_string(); self.advance()?; s }
-            _ => return Err(LolError::Syntax { expected: "variable name".into(), found: self.look.as_lexeme() })
-        };
+        let name = self.read_var_name()?;
 
+        self.skip_ws()?;
         self.expect_hash()?;
         self.expect_kw(Kw::Mkay)?;
-        self.push_node(Node::VarUse { name });
+        self.push_node(NodeKind::VarUse { name }, &start);
         Ok(())
     }
 
@@ -344,112 +863,305 @@ impl<'a> SyntaxAnalyzer for Parser<'a> {
     fn parse_body(&mut self) -> Result<()> {
         self.expect_kw(Kw::Gimmeh)?;
         self.skip_ws()?;
-        match self.look.clone() {
-            Token::Kw(Kw::Bold)    => self.parse_bold(),
-            Token::Kw(Kw::Italics) => self.parse_italics(),
-            Token::Kw(Kw::Newline) => self.parse_newline(),
-            Token::Kw(Kw::Soundz)  => self.parse_audio(),
-            Token::Kw(Kw::Vidz)    => self.parse_video(),
-            Token::Kw(Kw::Item)    => self.parse_list_items(),
-            Token::Kw(Kw::Title)   => self.parse_title(),
-            other => Err(LolError::Syntax { expected: "BOLD/ITALICS/NEWLINE/SOUNDZ/VIDZ/ITEM/TITLE".into(), found: other.as_lexeme() }),
+        match self.look.kind.clone() {
+            TokenKind::Kw(Kw::Bold)    => self.parse_bold(),
+            TokenKind::Kw(Kw::Italics) => self.parse_italics(),
+            TokenKind::Kw(Kw::Newline) => self.parse_newline(),
+            TokenKind::Kw(Kw::Soundz)  => self.parse_audio(),
+            TokenKind::Kw(Kw::Vidz)    => self.parse_video(),
+            TokenKind::Kw(Kw::Pikchur) => self.parse_image(),
+            TokenKind::Kw(Kw::Linkz)   => self.parse_link(),
+            TokenKind::Kw(Kw::Item)    => self.parse_list_items(),
+            TokenKind::Kw(Kw::Title)   => self.parse_title(),
+            TokenKind::Kw(Kw::Toc)     => self.parse_toc(),
+            _ => Err(self.error(&one_of(BODY_ELEMENTS))),
         }
     }
 
     fn parse_bold(&mut self) -> Result<()> {
+        let start = self.last_hash.clone();
         self.expect_kw(Kw::Bold)?;
-        let t = self.read_text_until_hash()?;
-        self.expect_hash()?;
-        self.expect_kw(Kw::Mkay)?;
-        self.push_node(Node::Bold(t));
+        let kids = self.parse_inline_until_mkay(&[Kw::Gimmeh, Kw::Bold], &start, Inline::Formatting)?;
+        self.push_node(NodeKind::Bold(kids), &start);
         Ok(())
     }
 
     fn parse_italics(&mut self) -> Result<()> {
+        let start = self.last_hash.clone();
         self.expect_kw(Kw::Italics)?;
-        let t = self.read_text_until_hash()?;
-        self.expect_hash()?;
-        self.expect_kw(Kw::Mkay)?;
-        self.push_node(Node::Italics(t));
+        let kids = self.parse_inline_until_mkay(&[Kw::Gimmeh, Kw::Italics], &start, Inline::Formatting)?;
+        self.push_node(NodeKind::Italics(kids), &start);
         Ok(())
     }
 
     fn parse_newline(&mut self) -> Result<()> {
+        let start = self.last_hash.clone();
         self.expect_kw(Kw::Newline)?;
-        self.push_node(Node::Newline);
+        self.push_node(NodeKind::Newline, &start);
         Ok(())
     }
 
     /// LIST block
     fn parse_list(&mut self) -> Result<()> {
+        let start = self.last_hash.clone();
+        self.expect_kw(Kw::List)?;
+        self.open_block(&[Kw::Maek, Kw::List], &start);
+        self.parse_block(Self::list_step);
+
+        let items = self.close_block();
+        self.push_node(NodeKind::List(items), &start);
+        Ok(())
+    }
+
+    /// NUMBRD LIST block:  MAEK NUMBRD LIST [start] [ROMAN|ALPHA] ... OIC
+    /// The style word's case picks the case of the numbering (ROMAN → I, roman → i).
+    fn parse_numbered_list(&mut self) -> Result<()> {
+        let start = self.last_hash.clone();
+        self.expect_kw(Kw::Numbrd)?;
+        self.skip_ws()?;
         self.expect_kw(Kw::List)?;
-        self.stack.push(vec![]);
 
+        let mut first: Option<u32> = None;
+        let mut style = NumberStyle::Decimal;
         loop {
             self.skip_ws()?;
-            match self.look {
-                Token::Hash => {
-                    self.advance()?;
-                    self.skip_ws()?;
-                    match &self.look {
-                        Token::Kw(Kw::Gimmeh) => self.parse_list_items()?,
-                        Token::Kw(Kw::OBTW)   => self.parse_comment()?,
-                        Token::Kw(Kw::OIC)    => { self.advance()?; break; }
-                        _ => return Err(LolError::Syntax { expected: "GIMMEH ITEM or OBTW or OIC".into(), found: self.look.as_lexeme() })
-                    }
+            let TokenKind::Word(w) = &self.look.kind else { break };
+            let upper = w.chars().next().is_some_and(|c| c.is_ascii_uppercase());
+            match w.to_ascii_uppercase().as_str() {
+                n if first.is_none() && n.bytes().all(|b| b.is_ascii_digit()) => {
+                    first = Some(n.parse().map_err(|_| self.error("a smaller start number"))?);
                 }
-                Token::Eof => return Err(LolError::Syntax { expected: "#OIC for LIST".into(), found: "<EOF>".into() }),
-                _ => return Err(LolError::Syntax { expected: "# in LIST".into(), found: self.look.as_lexeme() }),
+                "ROMAN" => style = if upper { NumberStyle::UpperRoman } else { NumberStyle::LowerRoman },
+                "ALPHA" => style = if upper { NumberStyle::UpperAlpha } else { NumberStyle::LowerAlpha },
+                _ => return Err(self.error("start number or ROMAN/ALPHA")),
             }
+            self.advance()?;
         }
 
-        let items = self.stack.pop().unwrap();
-        self.push_node(Node::List(items));
+        self.open_block(&[Kw::Maek, Kw::Numbrd, Kw::List], &start);
+        self.parse_block(Self::list_step);
+
+        let items = self.close_block();
+        self.push_node(NodeKind::OrderedList { start: first, style, items }, &start);
         Ok(())
     }
 
     fn parse_list_items(&mut self) -> Result<()> {
+        let start = self.last_hash.clone();
         self.expect_kw(Kw::Item)?;
-        let t = self.read_text_until_hash()?;
-        self.expect_hash()?;
-        self.expect_kw(Kw::Mkay)?;
-        self.push_node(Node::ListItem(vec![Node::Text(t)]));
+        let kids = self.parse_inline_until_mkay(&[Kw::Gimmeh, Kw::Item], &start, Inline::Item)?;
+        self.push_node(NodeKind::ListItem(kids), &start);
         Ok(())
     }
 
     fn parse_inner_list(&mut self) -> Result<()> { Ok(()) }
 
+    /// TABL block:  MAEK TABL (MAEK ROW ... OIC)* OIC
+    fn parse_table(&mut self) -> Result<()> {
+        let start = self.last_hash.clone();
+        self.expect_kw(Kw::Tabl)?;
+        self.open_block(&[Kw::Maek, Kw::Tabl], &start);
+        self.parse_block(Self::table_step);
+
+        let rows = self.close_block();
+        self.push_node(NodeKind::Table(rows), &start);
+        Ok(())
+    }
+
+    /// KODE block:  MAEK KODE [lang] NEWLINE ... NEWLINE OIC
+    /// The lexer hands the whole body over as one Raw token. Only the language
+    /// may follow KODE on its line; the code starts on the next line, and the
+    /// block ends at an #OIC alone on its own line.
+    fn parse_code_block(&mut self) -> Result<()> {
+        let start = self.last_hash.clone();
+        self.expect_kw(Kw::Kode)?;
+        let (raw, raw_span) = match &self.look.kind {
+            TokenKind::Raw(r) => (*r, self.look.span.clone()),
+            _ => return Err(self.error("code")),
+        };
+        self.advance()?;
+
+        let (first, rest) = raw.split_once('\n').unwrap_or((raw, ""));
+        let lang = first.split_whitespace().next().unwrap_or("");
+        let after = &first.trim_start()[lang.len()..];
+        let extra = after.trim();
+        if !extra.is_empty() {
+            // Code on the KODE line would be mistaken for the language.
+            let offset = first.len() - after.trim_start().len();
+            let col = raw_span.col + first[..offset].chars().count();
+            let at = raw_span.start + offset;
+            self.report(LolError::Syntax {
+                span: Span::new(raw_span.file.clone(), at, at + extra.len(), raw_span.line, col),
+                expected: "a new line after the language".into(),
+                found: extra.to_string(),
+                opened: None,
+            });
+        }
+
+        // The lexer stops only at the closing #OIC line or at the end of the file.
+        self.opened.push((start.to(&self.prev), &[Kw::Maek, Kw::Kode]));
+        if self.look.kind != TokenKind::Hash {
+            return Err(self.error(&format!("{} on its own line", Kw::OIC.annotation())));
+        }
+        self.advance()?;
+        self.skip_ws()?;
+        self.expect_kw(Kw::OIC)?;
+        self.opened.pop();
+
+        let lang = lang.to_string();
+        let text = rest.trim_end().to_string();
+        self.push_node(NodeKind::CodeBlock { lang, text }, &start);
+        Ok(())
+    }
+
+    /// ROW block:  MAEK ROW (GIMMEH [HEDR] CELL ... MKAY)* OIC
+    fn parse_table_row(&mut self) -> Result<()> {
+        let start = self.last_hash.clone();
+        self.expect_kw(Kw::Row)?;
+        self.open_block(&[Kw::Maek, Kw::Row], &start);
+        self.parse_block(Self::row_step);
+
+        let cells = self.close_block();
+        self.push_node(NodeKind::TableRow(cells), &start);
+        Ok(())
+    }
+
+    /// A cell holds the same content as a list item.
+    fn parse_table_cell(&mut self, header: bool) -> Result<()> {
+        let start = self.last_hash.clone();
+        self.expect_kw(Kw::Cell)?;
+        let what: &[Kw] = if header { &[Kw::Gimmeh, Kw::Hedr, Kw::Cell] } else { &[Kw::Gimmeh, Kw::Cell] };
+        let children = self.parse_inline_until_mkay(what, &start, Inline::Item)?;
+        self.push_node(NodeKind::TableCell { header, children }, &start);
+        Ok(())
+    }
+
     fn parse_audio(&mut self) -> Result<()> {
+        let start = self.last_hash.clone();
         self.expect_kw(Kw::Soundz)?;
         let a = self.read_text_until_hash()?;
         self.expect_hash()?;
         self.expect_kw(Kw::Mkay)?;
-        self.push_node(Node::Audio(a));
+        self.push_node(NodeKind::Audio(a), &start);
         Ok(())
     }
 
     fn parse_video(&mut self) -> Result<()> {
+        let start = self.last_hash.clone();
         self.expect_kw(Kw::Vidz)?;
         let a = self.read_text_until_hash()?;
         self.expect_hash()?;
         self.expect_kw(Kw::Mkay)?;
-        self.push_node(Node::Video(a));
+        self.push_node(NodeKind::Video(a), &start);
+        Ok(())
+    }
+
+    /// Image:  GIMMEH PIKCHUR url [#IT IZ alt text] #MKAY
+    fn parse_image(&mut self) -> Result<()> {
+        let start = self.last_hash.clone();
+        self.expect_kw(Kw::Pikchur)?;
+        let src = self.read_text_until_hash()?;
+        if src.is_empty() {
+            return Err(self.error("image address"));
+        }
+        self.expect_hash()?;
+        self.skip_ws()?;
+
+        let alt = match &self.look.kind {
+            TokenKind::Kw(Kw::It) => {
+                self.advance()?;
+                self.skip_ws()?;
+                self.expect_kw(Kw::Iz)?;
+                let alt = self.read_text_until_hash()?;
+                self.expect_hash()?;
+                self.skip_ws()?;
+                alt
+            }
+            _ => String::new(),
+        };
+        self.expect_kw(Kw::Mkay)?;
+        self.push_node(NodeKind::Image { src, alt }, &start);
+        Ok(())
+    }
+
+    /// Hyperlink:  GIMMEH LINKZ url [#IT IZ label] #MKAY
+    /// The label may hold the same inline elements as BOLD, but not another link.
+    fn parse_link(&mut self) -> Result<()> {
+        let start = self.last_hash.clone();
+        if self.opened.iter().any(|(_, what)| *what == [Kw::Gimmeh, Kw::Linkz]) {
+            return Err(self.error("link label text (links cannot be nested)"));
+        }
+        self.expect_kw(Kw::Linkz)?;
+        let href = self.read_text_until_hash()?;
+        if href.is_empty() {
+            return Err(self.error("link address"));
+        }
+        self.expect_hash()?;
+        self.skip_ws()?;
+
+        let children = match &self.look.kind {
+            TokenKind::Kw(Kw::Mkay) => { self.advance()?; vec![] }
+            TokenKind::Kw(Kw::It) => {
+                self.advance()?;
+                self.skip_ws()?;
+                self.expect_kw(Kw::Iz)?;
+                self.parse_inline_until_mkay(&[Kw::Gimmeh, Kw::Linkz], &start, Inline::Formatting)?
+            }
+            _ => return Err(self.error(&format!("{} or {}", Kw::Iz.annotation(), Kw::Mkay.annotation()))),
+        };
+        self.push_node(NodeKind::Link { href, children }, &start);
         Ok(())
     }
 
     /// Reads plain text tokens.
+    /// Escaped characters become their own `Literal` node so no later trimming touches them.
     fn parse_text(&mut self) -> Result<()> {
-        let mut s = String::new();
         loop {
-            match &self.look {
-                Token::Text(t) => { s.push_str(t); self.advance()?; }
-                Token::Word(w) => { s.push_str(w); self.advance()?; }
-                _ => break,
+            let start = self.look.span.clone();
+            let mut s = String::new();
+            while let TokenKind::Text(t) | TokenKind::Word(t) = &self.look.kind {
+                s.push_str(t);
+                self.advance()?;
+            }
+            let escaped = matches!(self.look.kind, TokenKind::Escaped(_));
+            // Whitespace-only text is dropped, unless an escape follows it.
+            if !s.trim().is_empty() || (escaped && !s.is_empty()) {
+                self.push_node(NodeKind::Text(s), &start);
+            }
+            if !escaped {
+                return Ok(());
+            }
+
+            let start = self.look.span.clone();
+            let mut lit = String::new();
+            while let TokenKind::Escaped(e) = &self.look.kind {
+                lit.push_str(e);
+                self.advance()?;
             }
+            self.push_node(NodeKind::Literal(lit), &start);
         }
-        if !s.trim().is_empty() {
-            self.push_node(Node::Text(s));
+    }
+}
+
+/// Trims the whitespace just inside an element, i.e. at the start of its
+/// first child and the end of its last one, and around any nested list.
+fn trim_edges(kids: &mut Vec<Node>) {
+    if let Some(Node { kind: NodeKind::Text(t), .. }) = kids.first_mut() {
+        *t = t.trim_start().to_string();
+    }
+    if let Some(Node { kind: NodeKind::Text(t), .. }) = kids.last_mut() {
+        *t = t.trim_end().to_string();
+    }
+    for i in 0..kids.len() {
+        if !matches!(kids[i].kind, NodeKind::List(_) | NodeKind::OrderedList { .. }) {
+            continue;
+        }
+        if i > 0 && let NodeKind::Text(t) = &mut kids[i - 1].kind {
+            *t = t.trim_end().to_string();
+        }
+        if let Some(Node { kind: NodeKind::Text(t), .. }) = kids.get_mut(i + 1) {
+            *t = t.trim_start().to_string();
         }
-        Ok(())
     }
+    kids.retain(|n| !matches!(&n.kind, NodeKind::Text(t) if t.is_empty()));
 }
@@ -1,29 +1,354 @@
-use crate::ast::Node;
-use crate::error::Result;
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::{Node, NodeKind};
+use crate::diagnostic::{Diagnostic, DiagnosticKind};
+use crate::error::{LolError, Result};
+use crate::span::Span;
 
 /// The Analyzer is responsible for semantic checks.
-/// For Phase 2, we are not enforcing many rules yet.
-/// It mainly passes the AST forward untouched.
+/// It keeps a chain of lexical scopes: the program scope at the bottom,
+/// and one nested scope for every PARAGRAF / LIST / NUMBRD LIST block we walk into.
 pub struct Analyzer<'a> {
     // We borrow the AST produced by the parser
     ast: &'a [Node],
+
+    // Scope chain, innermost scope last. Each scope maps name -> (value, where it was defined).
+    scopes: Vec<HashMap<String, (String, Span)>>,
+
+    // Where variables of blocks that have already ended were defined,
+    // so a use from a sibling block can point at them.
+    ended: HashMap<String, Span>,
+
+    // Problems that do not stop the compile (e.g. an image without alt text).
+    pub warnings: Vec<Diagnostic>,
+
+    // Level of the previous heading, to spot skipped levels.
+    last_heading: Option<u8>,
+
+    // Anchor ids handed out so far; every id in the page must be unique.
+    ids: HashSet<String>,
+
+    // Next number to try for each slug, so repeated titles stay cheap.
+    suffixes: HashMap<String, usize>,
+
+    // Where the page title was set; a page has only one.
+    title: Option<Span>,
 }
 
 impl<'a> Analyzer<'a> {
     /// Store a reference to the AST that we will check.
     pub fn new(ast: &'a [Node]) -> Self {
-        Self { ast }
+        Self { ast, scopes: vec![], ended: HashMap::new(), warnings: vec![], last_heading: None, ids: HashSet::new(), suffixes: HashMap::new(), title: None }
     }
 
-    /// This function is where semantic checks would normally happen.
-    /// Examples of semantic checks (not implemented yet):
-    /// - Making sure variables are defined before they are used.
-    /// - Ensuring TITLE only appears inside HEAD.
-    /// - Making sure LIST items are inside LIST blocks.
-    ///
-    /// For now, we simply return a clone of the AST.
+    /// Walks the AST and checks that every variable is defined before it is used.
+    /// Each `VarUse` is replaced with a `Text` node holding the resolved value,
+    /// and each `Toc` with a list of links to the sections, so the HTML
+    /// generator never has to know about variables or the table of contents.
     pub fn check(&mut self) -> Result<Vec<Node>> {
-        // Later we can modify nodes here before HTML generation.
-        Ok(self.ast.to_vec())
+        let ast = self.ast;
+        self.scopes = vec![HashMap::new()]; // program scope
+        self.ended.clear();
+        self.last_heading = None;
+        self.ids.clear();
+        self.suffixes.clear();
+        self.title = None;
+        let checked = self.check_nodes(ast)?;
+
+        // Sections may come after the TOC, so it is built once every id is known.
+        let toc = toc_items(&checked);
+        Ok(self.expand_toc(checked, &toc))
+    }
+
+    /// Checks a list of sibling nodes in the current scope.
+    fn check_nodes(&mut self, nodes: &[Node]) -> Result<Vec<Node>> {
+        let mut out = Vec::with_capacity(nodes.len());
+        for node in nodes {
+            // Only the first title is kept, so the page has exactly one <title>.
+            if matches!(node.kind, NodeKind::Title(_)) && self.duplicate_title(node) {
+                continue;
+            }
+            out.push(self.check_node(node)?);
+        }
+        Ok(out)
+    }
+
+    /// Checks one node, opening a new scope for PARAGRAF and LIST blocks.
+    fn check_node(&mut self, node: &Node) -> Result<Node> {
+        let kind = match &node.kind {
+            NodeKind::VarDef { name, value } => {
+                self.define(name, value, &node.span);
+                node.kind.clone()
+            }
+
+            NodeKind::VarUse { name } => NodeKind::Text(self.resolve(name, node)?),
+
+            NodeKind::Paragraph(kids) => NodeKind::Paragraph(self.check_scoped(kids)?),
+            NodeKind::Section { title, level, children, .. } => {
+                // The section title is a heading too, so later headings follow on from it.
+                self.last_heading = Some(*level);
+                let id = self.unique_id(&slugify(title));
+                NodeKind::Section { title: title.clone(), level: *level, id, children: self.check_scoped(children)? }
+            }
+            NodeKind::List(kids) => NodeKind::List(self.check_scoped(kids)?),
+            NodeKind::OrderedList { start, style, items } => NodeKind::OrderedList {
+                start: *start,
+                style: *style,
+                items: self.check_scoped(items)?,
+            },
+
+            NodeKind::Table(rows) => {
+                self.check_columns(rows)?;
+                NodeKind::Table(self.check_scoped(rows)?)
+            }
+
+            // Other containers share the scope of their parent.
+            NodeKind::Html(kids) => NodeKind::Html(self.check_nodes(kids)?),
+            NodeKind::Head(kids) => NodeKind::Head(self.check_nodes(kids)?),
+            NodeKind::Body(kids) => NodeKind::Body(self.check_nodes(kids)?),
+            NodeKind::ListItem(kids) => NodeKind::ListItem(self.check_nodes(kids)?),
+            NodeKind::TableRow(kids) => NodeKind::TableRow(self.check_nodes(kids)?),
+            NodeKind::TableCell { header, children } => NodeKind::TableCell {
+                header: *header,
+                children: self.check_nodes(children)?,
+            },
+            NodeKind::Bold(kids) => NodeKind::Bold(self.check_nodes(kids)?),
+            NodeKind::Italics(kids) => NodeKind::Italics(self.check_nodes(kids)?),
+            NodeKind::Link { href, children } => {
+                check_url(href, node)?;
+                NodeKind::Link { href: href.clone(), children: self.check_nodes(children)? }
+            }
+            NodeKind::Audio(src) | NodeKind::Video(src) => {
+                check_url(src, node)?;
+                node.kind.clone()
+            }
+
+            NodeKind::Heading { level, children, .. } => {
+                self.check_heading_level(*level, node)?;
+                let children = self.check_nodes(children)?;
+                let id = self.unique_id(&slugify(&plain_text(&children)));
+                NodeKind::Heading { level: *level, id, children }
+            }
+
+            // Pages must be accessible, so every image should describe itself.
+            NodeKind::Image { src, alt } => {
+                check_url(src, node)?;
+                if alt.trim().is_empty() {
+                    self.warnings.push(Diagnostic::warning(
+                        DiagnosticKind::Semantic,
+                        node.span.clone(),
+                        format!("image '{}' has no alt text; add '#IT IZ <description>' before #MKAY", src),
+                    ));
+                }
+                node.kind.clone()
+            }
+
+            other => other.clone(),
+        };
+        Ok(Node::new(kind, node.span.clone()))
+    }
+
+    /// Records the page title, or warns that `node` is a second one.
+    fn duplicate_title(&mut self, node: &Node) -> bool {
+        let Some(first) = &self.title else {
+            self.title = Some(node.span.clone());
+            return false;
+        };
+        let mut warning = Diagnostic::warning(
+            DiagnosticKind::Semantic,
+            node.span.clone(),
+            "the page already has a title; this one is ignored".to_string(),
+        );
+        warning.notes.push((first.clone(), "the title was set here".to_string()));
+        self.warnings.push(warning);
+        true
+    }
+
+    /// Checks the children of a block inside a fresh nested scope.
+    fn check_scoped(&mut self, nodes: &[Node]) -> Result<Vec<Node>> {
+        self.scopes.push(HashMap::new());
+        let out = self.check_nodes(nodes);
+        if let Some(scope) = self.scopes.pop() {
+            self.ended.extend(scope.into_iter().map(|(name, (_, span))| (name, span)));
+        }
+        out
+    }
+
+    /// Headings must be h1..h6, and should not jump down more than one level
+    /// at a time (h1 straight to h3), which confuses screen-reader navigation.
+    fn check_heading_level(&mut self, level: u32, node: &Node) -> Result<()> {
+        let Some(level) = u8::try_from(level).ok().filter(|l| (1..=6).contains(l)) else {
+            return Err(LolError::Semantic {
+                span: node.span.clone(),
+                msg: format!("heading level {} is out of range (expected 1 to 6)", level),
+                note: None,
+            });
+        };
+        if let Some(prev) = self.last_heading && level > prev + 1 {
+            self.warnings.push(Diagnostic::warning(
+                DiagnosticKind::Semantic,
+                node.span.clone(),
+                format!("heading level {} skips level {} (the previous heading is level {})", level, prev + 1, prev),
+            ));
+        }
+        self.last_heading = Some(level);
+        Ok(())
+    }
+
+    /// Every row of a table must have as many cells as the first row.
+    fn check_columns(&self, rows: &[Node]) -> Result<()> {
+        let cells = |row: &Node| match &row.kind {
+            NodeKind::TableRow(kids) => Some(kids.iter().filter(|k| matches!(k.kind, NodeKind::TableCell { .. })).count()),
+            _ => None,
+        };
+        let mut rows = rows.iter().filter_map(|r| cells(r).map(|n| (r, n)));
+        let Some((_, expected)) = rows.next() else { return Ok(()) };
+        for (row, n) in rows {
+            if n != expected {
+                return Err(LolError::Semantic {
+                    span: row.span.clone(),
+                    msg: format!("table row has {} cell(s) but the first row has {}", n, expected),
+                    note: None,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces every `Toc` in `nodes` with a list of the section links in `toc`.
+    fn expand_toc(&mut self, nodes: Vec<Node>, toc: &[Node]) -> Vec<Node> {
+        let mut out = Vec::with_capacity(nodes.len());
+        for node in nodes {
+            let kind = match node.kind {
+                NodeKind::Toc if toc.is_empty() => {
+                    self.warnings.push(Diagnostic::warning(
+                        DiagnosticKind::Semantic,
+                        node.span.clone(),
+                        "table of contents is empty; there is no #MAEK SECSHUN in this file".to_string(),
+                    ));
+                    continue;
+                }
+                NodeKind::Toc => NodeKind::List(toc.to_vec()),
+                NodeKind::Section { title, level, id, children } => {
+                    NodeKind::Section { title, level, id, children: self.expand_toc(children, toc) }
+                }
+                NodeKind::Html(kids) => NodeKind::Html(self.expand_toc(kids, toc)),
+                NodeKind::Body(kids) => NodeKind::Body(self.expand_toc(kids, toc)),
+                other => other,
+            };
+            out.push(Node::new(kind, node.span));
+        }
+        out
+    }
+
+    /// Returns `slug`, or `slug-2`, `slug-3`, ... if it is already taken.
+    fn unique_id(&mut self, slug: &str) -> String {
+        let mut id = slug.to_string();
+        let n = self.suffixes.entry(slug.to_string()).or_insert(1);
+        while self.ids.contains(&id) {
+            *n += 1;
+            id = format!("{}-{}", slug, n);
+        }
+        self.ids.insert(id.clone());
+        id
+    }
+
+    /// Defines (or redefines) a variable in the innermost scope.
+    fn define(&mut self, name: &str, value: &str, span: &Span) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), (value.to_string(), span.clone()));
+        }
+    }
+
+    /// Looks a variable up from the innermost scope outwards.
+    /// A name that only a block which has already ended defines is out of
+    /// scope rather than undefined, and the error points at that definition.
+    fn resolve(&self, name: &str, node: &Node) -> Result<String> {
+        if let Some((value, _)) = self.scopes.iter().rev().find_map(|scope| scope.get(name)) {
+            return Ok(value.clone());
+        }
+        Err(match self.ended.get(name) {
+            Some(defined) => LolError::Semantic {
+                span: node.span.clone(),
+                msg: format!("variable '{}' is not defined in this scope", name),
+                note: Some(Box::new((defined.clone(), format!("'{}' is defined here, in a block that has already ended", name)))),
+            },
+            None => LolError::Semantic {
+                span: node.span.clone(),
+                msg: format!("variable '{}' is used before it is defined", name),
+                note: None,
+            },
+        })
+    }
+}
+
+/// Addresses must not run code when clicked or loaded, so the schemes
+/// that can (javascript:, vbscript:, data:) are refused.
+fn check_url(url: &str, node: &Node) -> Result<()> {
+    // Browsers skip leading spaces and control characters and drop tabs and
+    // newlines anywhere, so "  java\tscript:" is still a script URL.
+    let url = url.trim_start_matches(|c: char| c <= ' ');
+    let Some((scheme, _)) = url.split_once(':') else { return Ok(()) };
+    let scheme: String = scheme.chars().filter(|c| !matches!(c, '\t' | '\n' | '\r')).collect();
+    if ["javascript", "vbscript", "data"].iter().any(|s| scheme.eq_ignore_ascii_case(s)) {
+        return Err(LolError::Semantic {
+            span: node.span.clone(),
+            msg: format!("address '{}' uses the {}: scheme, which can run code in the page", url.trim(), scheme.to_ascii_lowercase()),
+            note: None,
+        });
+    }
+    Ok(())
+}
+
+/// One list item per section in `nodes`, each holding a link to the section
+/// and, when it has subsections, a nested list of their items.
+fn toc_items(nodes: &[Node]) -> Vec<Node> {
+    let mut items = vec![];
+    for node in nodes {
+        match &node.kind {
+            NodeKind::Section { title, id, children, .. } => {
+                let span = node.span.clone();
+                let text = Node::new(NodeKind::Text(title.clone()), span.clone());
+                let link = NodeKind::Link { href: format!("#{}", id), children: vec![text] };
+                let mut kids = vec![Node::new(link, span.clone())];
+                let sub = toc_items(children);
+                if !sub.is_empty() {
+                    kids.push(Node::new(NodeKind::List(sub), span.clone()));
+                }
+                items.push(Node::new(NodeKind::ListItem(kids), span));
+            }
+            NodeKind::Html(kids) | NodeKind::Body(kids) => items.extend(toc_items(kids)),
+            _ => {}
+        }
+    }
+    items
+}
+
+/// The visible text of some (already checked) nodes, without markup.
+fn plain_text(nodes: &[Node]) -> String {
+    let mut out = String::new();
+    for node in nodes {
+        match &node.kind {
+            NodeKind::Text(t) | NodeKind::Literal(t) => out.push_str(t),
+            NodeKind::Newline => out.push(' '),
+            NodeKind::Bold(kids) | NodeKind::Italics(kids) => out.push_str(&plain_text(kids)),
+            NodeKind::Link { children, .. } => out.push_str(&plain_text(children)),
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Turns heading text into an anchor id: "Getting Started!" -> "getting-started".
+fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') && (c.is_whitespace() || c == '-' || c == '_') {
+            slug.push('-');
+        }
     }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() { "section".to_string() } else { slug.to_string() }
 }
@@ -0,0 +1,39 @@
+// span.rs
+// This file defines source locations.
+// Every token, AST node and error carries a Span so messages can point
+// at the exact place in the .lol file that caused them.
+
+use std::fmt;
+use std::rc::Rc;
+
+/// A region of a source file.
+/// - file: the name of the file the region belongs to
+/// - start/end: byte offsets into the source (end is exclusive)
+/// - line/col: 1-based position of `start`, for humans
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub file: Rc<str>,
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Span {
+    /// Creates a span covering `start..end` that begins at `line`/`col`.
+    pub fn new(file: Rc<str>, start: usize, end: usize, line: usize, col: usize) -> Self {
+        Self { file, start, end, line, col }
+    }
+
+    /// Returns a span from the start of `self` to the end of `other`.
+    pub fn to(&self, other: &Span) -> Span {
+        Span { end: other.end.max(self.end), ..self.clone() }
+    }
+}
+
+impl fmt::Display for Span {
+    // Prints the location as `file:line:col`, the format most editors understand.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.col)
+    }
+}
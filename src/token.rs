@@ -2,76 +2,302 @@
 // This file defines the tokens and keywords used by the lexer and parser.
 // Tokens are the smallest meaningful pieces of the language.
 
-/// All keywords in LOL code.
-/// We store them in an enum so the parser can match on them easily.
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
-pub enum Kw {
-    Hai, Kthxbye,      // program start / end
-    OBTW, TLDR,        // comments
-    Maek, Gimmeh, Head, Title, Paragraf, OIC,   // structural tags
-    Bold, Italics, Newline, Soundz, Vidz,       // formatting or media
-    List, Item,                                // lists
-    Lemme, See,                                // variable use
-    I, Haz, It, Iz,                            // variable definition
-    Mkay,                                       // closing marker
+use crate::span::Span;
+
+/// Declares the keyword registry: the `Kw` enum and the `KEYWORDS` table,
+/// one entry per keyword, in the same order. This is the only list of
+/// keywords; the lexer, error messages and docs all read from it.
+macro_rules! keywords {
+    ($( $kw:ident = $spelling:literal; )*) => {
+        /// All keywords in LOL code.
+        /// We store them in an enum so the parser can match on them easily.
+        #[derive(Copy, Clone, Debug, PartialEq, Eq)]
+        #[allow(clippy::upper_case_acronyms)] // names mirror the LOL keywords
+        pub enum Kw {
+            $($kw),*
+        }
+
+        /// Every keyword, in the order of `Kw`.
+        pub const KEYWORDS: &[Keyword] = &[
+            $(Keyword { kw: Kw::$kw, spelling: $spelling }),*
+        ];
+    };
+}
+
+/// One entry of the keyword registry.
+/// - spelling: how the keyword is written (any letter case is accepted)
+///
+/// Where a keyword may appear is decided by `ANNOTATIONS`.
+#[derive(Debug)]
+pub struct Keyword {
+    pub kw: Kw,
+    pub spelling: &'static str,
+}
+
+keywords! {
+    Hai      = "HAI";
+    Kthxbye  = "KTHXBYE";
+    OBTW     = "OBTW";
+    TLDR     = "TLDR";
+    Maek     = "MAEK";
+    Gimmeh   = "GIMMEH";
+    Head     = "HEAD";
+    Title    = "TITLE";
+    Paragraf = "PARAGRAF";
+    OIC      = "OIC";
+    Hedr     = "HEDR";
+    Secshun  = "SECSHUN";
+    Toc      = "TOC";
+    Bold     = "BOLD";
+    Italics  = "ITALICS";
+    Newline  = "NEWLINE";
+    Soundz   = "SOUNDZ";
+    Vidz     = "VIDZ";
+    Pikchur  = "PIKCHUR";
+    Linkz    = "LINKZ";
+    List     = "LIST";
+    Item     = "ITEM";
+    Numbrd   = "NUMBRD";
+    Tabl     = "TABL";
+    Row      = "ROW";
+    Cell     = "CELL";
+    Kode     = "KODE";
+    Lemme    = "LEMME";
+    See      = "SEE";
+    I        = "I";
+    Haz      = "HAZ";
+    It       = "IT";
+    Iz       = "IZ";
+    Mkay     = "MKAY";
+}
+
+/// One entry of the annotation registry: a keyword sequence written after '#'.
+/// - words: the keywords, in order, e.g. MAEK NUMBRD LIST
+/// - end: the keyword that closes it after a '#' (None: nothing does)
+/// - rule: its nonterminal in Design/BNF.txt ("" when it is only part of other rules)
+/// - body: what comes between the words and the end, in BNF
+/// - doc: a one-line description, for generated documentation
+#[derive(Debug)]
+pub struct Annotation {
+    pub words: &'static [Kw],
+    pub end: Option<Kw>,
+    pub rule: &'static str,
+    pub body: &'static str,
+    pub doc: &'static str,
+}
+
+/// Every annotation. When a keyword is part of several, the first one
+/// containing it is the one error messages suggest.
+pub const ANNOTATIONS: &[Annotation] = &[
+    ann(&[Kw::Hai], Some(Kw::Kthxbye), "lolcode", "<optional_comment> <optional_head> <body>", "The whole program"),
+    ann(&[Kw::OBTW], Some(Kw::TLDR), "comment", "<text>", "Comment"),
+    ann(&[Kw::Maek, Kw::Head], Some(Kw::OIC), "head", "<comment_opt> <title>", "Page head block"),
+    ann(&[Kw::Gimmeh, Kw::Title], Some(Kw::Mkay), "title", "<text>", "Page title, inside HEAD"),
+    ann(&[Kw::Maek, Kw::Paragraf], Some(Kw::OIC), "paragraph", "<inner_paragraph>", "Paragraph block"),
+    ann(&[Kw::Maek, Kw::Hedr], Some(Kw::OIC), "heading", "<digits> <inline_list>", "Heading block with a level 1-6"),
+    ann(&[Kw::Maek, Kw::Secshun], Some(Kw::OIC), "section", "<text> <paragraph_list>", "Titled section block with an anchor"),
+    ann(&[Kw::Gimmeh, Kw::Toc], Some(Kw::Mkay), "toc", "", "Table of contents of the sections"),
+    ann(&[Kw::Gimmeh, Kw::Bold], Some(Kw::Mkay), "bold", "<inline_list>", "Bold text"),
+    ann(&[Kw::Gimmeh, Kw::Italics], Some(Kw::Mkay), "italics", "<inline_list>", "Italic text"),
+    ann(&[Kw::Gimmeh, Kw::Newline], None, "newline", "", "Line break"),
+    ann(&[Kw::Gimmeh, Kw::Soundz], Some(Kw::Mkay), "audio", "<address>", "Audio player for a URL"),
+    ann(&[Kw::Gimmeh, Kw::Vidz], Some(Kw::Mkay), "video", "<address>", "Embedded video for a URL"),
+    ann(&[Kw::Gimmeh, Kw::Pikchur], Some(Kw::Mkay), "image", "<address> <alt_text>", "Image for a URL, with #IT IZ alt text"),
+    ann(&[Kw::Gimmeh, Kw::Linkz], Some(Kw::Mkay), "link", "<address> <link_label>", "Link to a URL, with an optional #IT IZ label"),
+    ann(&[Kw::Maek, Kw::List], Some(Kw::OIC), "list", "<list_items>", "Bulleted list block"),
+    ann(&[Kw::Maek, Kw::Numbrd, Kw::List], Some(Kw::OIC), "list", "<number_options> <list_items>", "Numbered list block, with an optional start and style"),
+    ann(&[Kw::Gimmeh, Kw::Item], Some(Kw::Mkay), "item", "<item_content>", "List item"),
+    ann(&[Kw::Maek, Kw::Tabl], Some(Kw::OIC), "table", "<rows>", "Table block"),
+    ann(&[Kw::Maek, Kw::Row], Some(Kw::OIC), "row", "<cells>", "Table row block"),
+    ann(&[Kw::Gimmeh, Kw::Cell], Some(Kw::Mkay), "cell", "<item_content>", "Table cell"),
+    ann(&[Kw::Gimmeh, Kw::Hedr, Kw::Cell], Some(Kw::Mkay), "cell", "<item_content>", "Table header cell"),
+    ann(&[Kw::Maek, Kw::Kode], Some(Kw::OIC), "code", "<lang_opt> <line_break> <raw_text> <line_break>", "Verbatim code block; #OIC alone on a line ends it"),
+    ann(&[Kw::Lemme, Kw::See], Some(Kw::Mkay), "variable_use", "<varname>", "Inserts a variable's value"),
+    ann(&[Kw::I, Kw::Haz], Some(Kw::Mkay), "variable_define", "<varname> \"#IT\" \"IZ\" <value>", "Defines a variable"),
+    ann(&[Kw::It, Kw::Iz], None, "", "", "Gives a variable's value, a link's label or an image's alt text"),
+];
+
+const fn ann(words: &'static [Kw], end: Option<Kw>, rule: &'static str, body: &'static str, doc: &'static str) -> Annotation {
+    Annotation { words, end, rule, body, doc }
+}
+
+/// Per keyword: whether it may come right after '#' (it starts or ends an
+/// annotation), and whether the word after it is read as a keyword too.
+const ROLES: [(bool, bool); KEYWORDS.len()] = {
+    let mut roles = [(false, false); KEYWORDS.len()];
+    let mut a = 0;
+    while a < ANNOTATIONS.len() {
+        let words = ANNOTATIONS[a].words;
+        roles[words[0] as usize].0 = true;
+        if let Some(end) = ANNOTATIONS[a].end {
+            roles[end as usize].0 = true;
+        }
+        let mut w = 0;
+        while w + 1 < words.len() {
+            roles[words[w] as usize].1 = true;
+            w += 1;
+        }
+        a += 1;
+    }
+    roles
+};
+
+impl Kw {
+    /// This keyword's registry entry.
+    pub fn info(self) -> &'static Keyword {
+        &KEYWORDS[self as usize]
+    }
+
+    /// The keyword as it is written in source, e.g. "PARAGRAF".
+    pub fn as_str(self) -> &'static str {
+        self.info().spelling
+    }
+
+    /// Whether the keyword may come directly after '#'.
+    /// The rest only make sense after another keyword (e.g. HEAD after MAEK).
+    pub fn starts_annotation(self) -> bool {
+        ROLES[self as usize].0
+    }
+
+    /// Whether the word after this keyword is read as a keyword too (e.g. MAEK HEAD).
+    pub fn next_is_keyword(self) -> bool {
+        ROLES[self as usize].1
+    }
+
+    /// The whole annotation the keyword belongs to, e.g. "#MAEK HEAD" for HEAD
+    /// or "#MAEK NUMBRD LIST" for NUMBRD. Closing keywords, and openers shared
+    /// by several annotations, stand alone ("#OIC", "#GIMMEH").
+    pub fn annotation(self) -> String {
+        let opens = ANNOTATIONS.iter().filter(|a| a.words[0] == self).count();
+        match ANNOTATIONS.iter().find(|a| a.words.contains(&self)) {
+            Some(a) if opens < 2 => a.spelling(),
+            _ => format!("#{}", self.as_str()),
+        }
+    }
+}
+
+impl Annotation {
+    /// How the annotation is written, e.g. "#MAEK NUMBRD LIST".
+    pub fn spelling(&self) -> String {
+        format!("#{}", one_of(&[self.words]))
+    }
+
+    /// Its BNF alternative, e.g. `"#MAEK" "LIST" <list_items> "#OIC"`.
+    fn production(&self) -> String {
+        let mut parts: Vec<String> = self.words.iter().map(|k| format!("\"{}\"", k.as_str())).collect();
+        parts[0].insert(1, '#');
+        if !self.body.is_empty() {
+            parts.push(self.body.to_string());
+        }
+        if let Some(end) = self.end {
+            parts.push(format!("\"#{}\"", end.as_str()));
+        }
+        parts.join(" ")
+    }
+}
+
+/// Looks a word up as a keyword, ignoring letter case.
+pub fn lookup(word: &str) -> Option<Kw> {
+    KEYWORDS.iter().find(|k| k.spelling.eq_ignore_ascii_case(word)).map(|k| k.kw)
 }
 
-/// Tokens are what the lexer outputs to the parser.
+/// Lists the keyword sequences an error message expects, e.g. "GIMMEH/MAEK LIST".
+pub fn one_of(choices: &[&[Kw]]) -> String {
+    let choice = |c: &&[Kw]| c.iter().map(|k| k.as_str()).collect::<Vec<_>>().join(" ");
+    choices.iter().map(choice).collect::<Vec<_>>().join("/")
+}
+
+/// A reference table of every annotation, generated from the registry.
+/// Printed by `lolmarkdownn --keywords`.
+pub fn keyword_reference() -> String {
+    let usage = |a: &Annotation| match a.end {
+        Some(end) => format!("{} ... #{}", a.spelling(), end.as_str()),
+        None => a.spelling(),
+    };
+    let width = ANNOTATIONS.iter().map(|a| usage(a).len()).max().unwrap_or(0);
+    let mut out = String::new();
+    for a in ANNOTATIONS {
+        out.push_str(&format!("{:<width$}  {}\n", usage(a), a.doc));
+    }
+    out
+}
+
+/// The BNF productions of every annotation, generated from the registry.
+/// Annotations sharing a rule become alternatives of one production.
+/// Design/BNF.txt holds these along with the rules that combine them;
+/// printed by `lolmarkdownn --grammar`.
+pub fn grammar() -> String {
+    let mut out = String::new();
+    let mut done: Vec<&str> = vec![];
+    for a in ANNOTATIONS.iter().filter(|a| !a.rule.is_empty()) {
+        if done.contains(&a.rule) {
+            continue;
+        }
+        done.push(a.rule);
+        let head = format!("<{}> ::= ", a.rule);
+        for (i, alt) in ANNOTATIONS.iter().filter(|b| b.rule == a.rule).enumerate() {
+            if i == 0 {
+                out.push_str(&head);
+            } else {
+                out.push_str(&format!("{:indent$}| ", "", indent = head.len() - 2));
+            }
+            out.push_str(&alt.production());
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// The kinds of token the lexer outputs to the parser.
 /// - Hash: `#` indicates the start of a command or block
 /// - Word: alphabetic/identifier text
 /// - Text: punctuation or whitespace text
 /// - Kw: recognized keyword
+/// - Escaped: a character written with a `\` escape; never special, never trimmed
+/// - Raw: the verbatim body of a KODE block, up to (not including) its `#OIC`
 /// - Eof: end of input
+///
+/// The text of Word/Text/Escaped/Raw borrows from the source.
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub enum Token {
+pub enum TokenKind<'a> {
     Hash,
-    Word(String),
-    Text(String),
+    Word(&'a str),
+    Text(&'a str),
     Kw(Kw),
+    Escaped(&'a str),
+    Raw(&'a str),
     Eof,
 }
 
-impl Token {
+impl TokenKind<'_> {
     /// Convert a token back to the text form used in error messages.
     pub fn as_lexeme(&self) -> String {
         match self {
-            Token::Hash => "#".into(),
-            Token::Word(w) => w.clone(),
-            Token::Text(t) => t.clone(),
-            Token::Kw(k) => format!("{:?}", k),
-            Token::Eof => "<EOF>".into(),
+            TokenKind::Hash => "#".into(),
+            TokenKind::Word(w) => w.to_string(),
+            TokenKind::Text(t) => t.to_string(),
+            TokenKind::Kw(k) => k.as_str().into(),
+            TokenKind::Escaped(e) => format!("\\{}", e),
+            TokenKind::Raw(_) => "code".into(),
+            TokenKind::Eof => "<EOF>".into(),
         }
     }
 }
 
-/// Convert a string to a keyword if it matches.
-/// This is used by the lexer when detecting commands after `#` or `GIMMEH`.
-pub fn map_kw(s: &str) -> Option<Kw> {
-    match s.to_ascii_uppercase().as_str() {
-        "HAI" => Some(Kw::Hai),
-        "KTHXBYE" => Some(Kw::Kthxbye),
-        "OBTW" => Some(Kw::OBTW),
-        "TLDR" => Some(Kw::TLDR),
-        "MAEK" => Some(Kw::Maek),
-        "GIMMEH" => Some(Kw::Gimmeh),
-        "OIC" => Some(Kw::OIC),
-        "MKAY" => Some(Kw::Mkay),
-        "HEAD" => Some(Kw::Head),
-        "TITLE" => Some(Kw::Title),
-        "PARAGRAF" => Some(Kw::Paragraf),
-        "BOLD" => Some(Kw::Bold),
-        "ITALICS" => Some(Kw::Italics),
-        "LIST" => Some(Kw::List),
-        "ITEM" => Some(Kw::Item),
-        "NEWLINE" => Some(Kw::Newline),
-        "SOUNDZ" => Some(Kw::Soundz),
-        "VIDZ" => Some(Kw::Vidz),
-        "I" => Some(Kw::I),
-        "HAZ" => Some(Kw::Haz),
-        "IT" => Some(Kw::It),
-        "IZ" => Some(Kw::Iz),
-        "LEMME" => Some(Kw::Lemme),
-        "SEE" => Some(Kw::See),
-        _ => None,
+/// A token together with the source region it was read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub kind: TokenKind<'a>,
+    pub span: Span,
+}
+
+impl<'a> Token<'a> {
+    pub fn new(kind: TokenKind<'a>, span: Span) -> Self {
+        Self { kind, span }
+    }
+
+    /// Convert a token back to the text form used in error messages.
+    pub fn as_lexeme(&self) -> String {
+        self.kind.as_lexeme()
     }
 }
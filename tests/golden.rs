@@ -0,0 +1,102 @@
+// Golden-file conformance suite.
+// Every test/*.lol is compiled and the result compared with a checked-in
+// snapshot in test/expected/:
+// - <name>.html when the file compiles (legacy format)
+// - <name>.err  when it fails: each diagnostic rendered without color, which
+//               records the error class, message and file:line:col
+//
+// After an intended change in output, update the snapshots with
+//     BLESS=1 cargo test --test golden
+// and review the diff before committing.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use lolmarkdownn::{compile, HtmlFormat, Options};
+
+/// The snapshot file and its expected contents for one input.
+fn render(path: &Path) -> (&'static str, String) {
+    let source = fs::read_to_string(path).unwrap();
+    let name = format!("test/{}", path.file_name().unwrap().to_string_lossy());
+    let options = Options { file: name, format: HtmlFormat::Legacy };
+    match compile(&source, options) {
+        Ok(out) => ("html", out.html),
+        Err(diagnostics) => {
+            let text: Vec<String> = diagnostics.iter().map(|d| d.render(&source, false)).collect();
+            ("err", text.join("\n"))
+        }
+    }
+}
+
+/// A short description of where `actual` first differs from `expected`.
+fn first_difference(expected: &str, actual: &str) -> String {
+    let (mut e, mut a) = (expected.lines(), actual.lines());
+    for line in 1.. {
+        match (e.next(), a.next()) {
+            (Some(x), Some(y)) if x == y => continue,
+            (None, None) => break,
+            (x, y) => {
+                return format!(
+                    "line {}:\n    expected: {}\n    actual:   {}",
+                    line,
+                    x.unwrap_or("<end of file>"),
+                    y.unwrap_or("<end of file>")
+                );
+            }
+        }
+    }
+    "files differ only in line endings or a trailing newline".to_string()
+}
+
+#[test]
+fn golden_files() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let expected_dir = root.join("test").join("expected");
+    let bless = std::env::var_os("BLESS").is_some();
+
+    let mut inputs: Vec<PathBuf> = fs::read_dir(root.join("test"))
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|e| e == "lol"))
+        .collect();
+    inputs.sort();
+    assert!(!inputs.is_empty(), "no .lol files found in test/");
+
+    let mut failures = vec![];
+    for input in &inputs {
+        let stem = input.file_stem().unwrap().to_string_lossy().to_string();
+        let (ext, actual) = render(input);
+        let snapshot = expected_dir.join(format!("{}.{}", stem, ext));
+        let other = expected_dir.join(format!("{}.{}", stem, if ext == "html" { "err" } else { "html" }));
+
+        if bless {
+            fs::create_dir_all(&expected_dir).unwrap();
+            fs::write(&snapshot, &actual).unwrap();
+            if other.exists() {
+                fs::remove_file(&other).unwrap();
+            }
+            continue;
+        }
+
+        if other.exists() {
+            failures.push(format!("{}: expected {}, but got .{} output", stem, other.display(), ext));
+            continue;
+        }
+        match fs::read_to_string(&snapshot) {
+            Ok(expected) => {
+                let expected = expected.replace("\r\n", "\n");
+                if expected != actual {
+                    failures.push(format!("{}: {} differs at {}", stem, snapshot.display(), first_difference(&expected, &actual)));
+                }
+            }
+            Err(_) => failures.push(format!("{}: missing snapshot {}", stem, snapshot.display())),
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} golden file(s) drifted (run with BLESS=1 to accept the new output):\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,364 @@
+// Integration tests that drive the compiler through its library API.
+
+use lolmarkdownn::{compile, analyze, generate, parse};
+use lolmarkdownn::{DiagnosticKind, HtmlFormat, NodeKind, Options, Severity};
+
+fn options() -> Options {
+    Options { file: "doc.lol".to_string(), format: HtmlFormat::Legacy }
+}
+
+#[test]
+fn compiles_a_paragraph() {
+    let out = compile("#HAI #MAEK PARAGRAF hello #GIMMEH BOLD world #MKAY #OIC #KTHXBYE", options()).unwrap();
+    assert!(out.html.starts_with("<html>\n"));
+    assert!(out.html.contains("<p> hello <b> world </b></p>"), "{}", out.html);
+    assert!(out.warnings.is_empty());
+}
+
+#[test]
+fn html5_format_adds_doctype_and_body() {
+    let opts = Options { format: HtmlFormat::Html5, ..options() };
+    let out = compile("#HAI #MAEK PARAGRAF hi #OIC #KTHXBYE", opts).unwrap();
+    assert!(out.html.starts_with("<!DOCTYPE html>\n"));
+    assert!(out.html.contains("<body>"));
+    assert!(out.html.contains("<title>Untitled</title>"));
+}
+
+#[test]
+fn variables_are_resolved() {
+    let src = "#HAI #I HAZ who #IT IZ world #MKAY #MAEK PARAGRAF hello #LEMME SEE who #MKAY #OIC #KTHXBYE";
+    let out = compile(src, options()).unwrap();
+    assert!(out.html.contains("hello world"), "{}", out.html);
+}
+
+#[test]
+fn syntax_errors_carry_their_location() {
+    let src = "#HAI\n#MAEK PARAGRAF oops\n#KTHXBYE\n";
+    let errs = compile(src, options()).unwrap_err();
+    assert_eq!(errs.len(), 1);
+    let e = &errs[0];
+    assert_eq!(e.kind, DiagnosticKind::Syntax);
+    assert_eq!(e.severity, Severity::Error);
+    assert_eq!(e.message, "expected #OIC, found KTHXBYE");
+    assert_eq!((&*e.span.file, e.span.line, e.span.col), ("doc.lol", 3, 2));
+    assert_eq!(e.notes.len(), 1);
+    assert_eq!(e.notes[0].0.line, 2);
+}
+
+#[test]
+fn every_lexical_error_is_reported() {
+    let errs = compile("#HAI #HEAD #MAEK PARAGRAF x #OIC #END #KTHXBYE", options()).unwrap_err();
+    assert_eq!(errs.len(), 2);
+    assert!(errs.iter().all(|e| e.kind == DiagnosticKind::Lexical));
+    assert!(errs[0].message.contains("did you mean '#MAEK HEAD'"), "{}", errs[0].message);
+    assert_eq!(errs[1].message, "unknown annotation '#END'");
+}
+
+#[test]
+fn close_misspellings_get_a_suggestion() {
+    let errs = compile("#HAI #PARAGRAPH x #OIC #GIMME #KTHXBYE", options()).unwrap_err();
+    assert_eq!(errs[0].message, "unknown annotation '#PARAGRAPH'; did you mean '#MAEK PARAGRAF'?");
+    assert_eq!(errs[1].message, "unknown annotation '#GIMME'; did you mean '#GIMMEH'?");
+}
+
+#[test]
+fn suggestions_name_the_whole_annotation() {
+    let errs = compile("#HAI #is x #IT IZ 1 #MKAY #NUMBRED LIST #OIC #KTHXBYE", options()).unwrap_err();
+    assert_eq!(errs[0].message, "unknown annotation '#is'; did you mean '#I HAZ'?");
+    assert_eq!(errs[1].message, "unknown annotation '#NUMBRED'; did you mean '#MAEK NUMBRD LIST'?");
+}
+
+#[test]
+fn undefined_variable_is_a_semantic_error() {
+    let errs = compile("#HAI #LEMME SEE nobody #MKAY #KTHXBYE", options()).unwrap_err();
+    assert_eq!(errs.len(), 1);
+    assert_eq!(errs[0].kind, DiagnosticKind::Semantic);
+    assert!(errs[0].message.contains("'nobody'"));
+}
+
+#[test]
+fn variable_from_an_ended_block_points_at_its_definition() {
+    let src = "#HAI\n#MAEK PARAGRAF #I HAZ x #IT IZ 1 #MKAY #OIC\n#MAEK PARAGRAF #LEMME SEE x #MKAY #OIC\n#KTHXBYE\n";
+    let errs = compile(src, options()).unwrap_err();
+    assert_eq!(errs.len(), 1);
+    assert_eq!(errs[0].message, "variable 'x' is not defined in this scope");
+    assert_eq!(errs[0].span.line, 3);
+    assert_eq!(errs[0].notes.len(), 1);
+    assert_eq!(errs[0].notes[0].0.line, 2);
+    assert_eq!(errs[0].notes[0].1, "'x' is defined here, in a block that has already ended");
+}
+
+#[test]
+fn warnings_do_not_stop_the_compile() {
+    let out = compile("#HAI #GIMMEH PIKCHUR cat.png #MKAY #KTHXBYE", options()).unwrap();
+    assert!(out.html.contains("<img src=\"cat.png\" alt=\"\">"));
+    assert_eq!(out.warnings.len(), 1);
+    assert_eq!(out.warnings[0].severity, Severity::Warning);
+}
+
+#[test]
+fn stages_can_run_one_at_a_time() {
+    let src = "#HAI #MAEK SECSHUN Intro #MAEK PARAGRAF hi #OIC #OIC #GIMMEH TOC #MKAY #KTHXBYE";
+    let ast = parse(src, "doc.lol").unwrap();
+    assert!(matches!(ast[0].kind, NodeKind::Section { .. }));
+    assert!(matches!(ast[1].kind, NodeKind::Toc));
+
+    let (checked, warnings) = analyze(&ast).unwrap();
+    assert!(warnings.is_empty());
+    match &checked[0].kind {
+        NodeKind::Section { id, .. } => assert_eq!(id, "intro"),
+        other => panic!("expected a section, got {:?}", other),
+    }
+    assert!(matches!(checked[1].kind, NodeKind::List(_)));
+
+    let html = generate(&checked, HtmlFormat::Legacy);
+    assert!(html.contains("<a href=\"#intro\">Intro</a>"), "{}", html);
+    assert!(html.contains("<section id=\"intro\">"));
+}
+
+#[test]
+fn diagnostics_render_against_the_source() {
+    let src = "#HAI\n#LEMME SEE x #MKAY\n#KTHXBYE\n";
+    let errs = compile(src, options()).unwrap_err();
+    let text = errs[0].render(src, false);
+    assert!(text.starts_with("semantic error: variable 'x' is used before it is defined\n"), "{}", text);
+    assert!(text.contains(" --> doc.lol:2:1"), "{}", text);
+    assert!(text.contains("2 | #LEMME SEE x #MKAY"), "{}", text);
+}
+
+#[test]
+fn lexer_iterates_over_borrowed_tokens() {
+    use lolmarkdownn::lexer::CharLexer;
+    use lolmarkdownn::token::{Kw, TokenKind};
+
+    let src = String::from("#HAI hello, \\# #KTHXBYE");
+    let tokens: Vec<_> = CharLexer::new(&src, "doc.lol").collect::<Result<_, _>>().unwrap();
+    let kinds: Vec<&TokenKind> = tokens.iter().map(|t| &t.kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            &TokenKind::Hash,
+            &TokenKind::Kw(Kw::Hai),
+            &TokenKind::Text(" "),
+            &TokenKind::Word("hello"),
+            &TokenKind::Text(","),
+            &TokenKind::Text(" "),
+            &TokenKind::Escaped("#"),
+            &TokenKind::Text(" "),
+            &TokenKind::Hash,
+            &TokenKind::Kw(Kw::Kthxbye),
+        ]
+    );
+
+    // Word text is a slice of the source, found at the token's span.
+    let TokenKind::Word(w) = tokens[3].kind else { unreachable!() };
+    assert_eq!(w.as_ptr(), src[tokens[3].span.start..].as_ptr());
+}
+
+#[test]
+fn keyword_registry_drives_spelling_and_lookup() {
+    use lolmarkdownn::token::{lookup, Kw, TokenKind, KEYWORDS};
+
+    for (i, k) in KEYWORDS.iter().enumerate() {
+        assert_eq!(k.kw as usize, i, "{} is out of order", k.spelling);
+        assert_eq!(lookup(k.spelling), Some(k.kw));
+        assert_eq!(lookup(&k.spelling.to_ascii_lowercase()), Some(k.kw));
+        assert_eq!(TokenKind::Kw(k.kw).as_lexeme(), k.spelling);
+    }
+    assert_eq!(lookup("PARAGRAPH"), None);
+    assert_eq!(Kw::Paragraf.annotation(), "#MAEK PARAGRAF");
+    assert_eq!(Kw::Mkay.annotation(), "#MKAY");
+}
+
+#[test]
+fn annotations_cover_every_keyword() {
+    use lolmarkdownn::token::{Kw, ANNOTATIONS, KEYWORDS};
+
+    let missing: Vec<&str> = KEYWORDS
+        .iter()
+        .filter(|k| !ANNOTATIONS.iter().any(|a| a.words.contains(&k.kw) || a.end == Some(k.kw)))
+        .map(|k| k.spelling)
+        .collect();
+    assert!(missing.is_empty(), "no annotation uses {:?}", missing);
+
+    // Multi-word annotations are described whole; their fragments are not annotations.
+    assert_eq!(Kw::Numbrd.annotation(), "#MAEK NUMBRD LIST");
+    assert_eq!(Kw::I.annotation(), "#I HAZ");
+    assert!(!Kw::Haz.starts_annotation() && !Kw::Cell.starts_annotation());
+    assert!(Kw::Hedr.next_is_keyword() && !Kw::Cell.next_is_keyword());
+
+    let reference = lolmarkdownn::token::keyword_reference();
+    assert!(reference.lines().any(|l| l.starts_with("#GIMMEH HEDR CELL ... #MKAY ")), "{}", reference);
+    assert!(reference.lines().any(|l| l.starts_with("#MAEK NUMBRD LIST ... #OIC ")), "{}", reference);
+    for fragment in ["#LEMME", "#I", "#IT"] {
+        assert!(!reference.lines().any(|l| l.split("  ").next() == Some(fragment)), "{} listed alone:\n{}", fragment, reference);
+    }
+}
+
+#[test]
+fn grammar_holds_the_generated_annotation_rules() {
+    let bnf = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/Design/BNF.txt")).unwrap();
+    let generated = lolmarkdownn::token::grammar();
+    assert!(
+        bnf.contains(&generated),
+        "Design/BNF.txt is out of date; replace its annotation rules with `lolmarkdownn --grammar`:\n{}",
+        generated
+    );
+}
+
+#[test]
+fn tokens_dump_lists_each_token_with_its_position() {
+    let (text, errors) = lolmarkdownn::dump::tokens("#HAI\n#END hi", "doc.lol");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].kind, DiagnosticKind::Lexical);
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines[0], "1:1      Hash     #");
+    assert_eq!(lines[1], "1:2      Kw       HAI");
+    assert_eq!(lines[2], "1:5      Text     \"\\n\"");
+    assert!(lines.contains(&"2:6      Word     \"hi\""), "{}", text);
+    assert_eq!(lines.last(), Some(&"2:8      Eof"));
+}
+
+#[test]
+fn ast_dumps_show_the_tree_and_json() {
+    use lolmarkdownn::dump;
+
+    let ast = parse("#HAI\n#MAEK PARAGRAF say \"hi\" #GIMMEH BOLD now #MKAY #OIC #KTHXBYE", "doc.lol").unwrap();
+    assert_eq!(
+        dump::tree(&ast),
+        "Paragraph @2:1\n  Text @2:16 text=\"say \\\"hi\\\" \"\n  Bold @2:25\n    Text @2:37 text=\"now\"\n"
+    );
+    assert_eq!(
+        dump::json(&ast),
+        concat!(
+            "[\n",
+            "  {\"kind\": \"Paragraph\", \"line\": 2, \"col\": 1, \"children\": [\n",
+            "    {\"kind\": \"Text\", \"line\": 2, \"col\": 16, \"text\": \"say \\\"hi\\\" \"},\n",
+            "    {\"kind\": \"Bold\", \"line\": 2, \"col\": 25, \"children\": [\n",
+            "      {\"kind\": \"Text\", \"line\": 2, \"col\": 37, \"text\": \"now\"}\n",
+            "    ]}\n",
+            "  ]}\n",
+            "]\n",
+        )
+    );
+}
+
+#[test]
+fn code_blocks_end_at_an_oic_line() {
+    // Annotations inside the code, even unbalanced ones, are kept verbatim.
+    let src = "#HAI\n#MAEK KODE python\nif line.startswith(\"#MAEK\"):\n    pass  # #OIC\n  #OIC\n#KTHXBYE\n";
+    let out = compile(src, options()).unwrap();
+    assert!(
+        out.html.contains("<pre><code class=\"language-python\">if line.startswith(\"#MAEK\"):\n    pass  # #OIC</code></pre>"),
+        "{}",
+        out.html
+    );
+}
+
+#[test]
+fn code_blocks_take_only_a_language_on_the_kode_line() {
+    let errs = compile("#HAI\n#MAEK KODE x = 1\n#OIC\n#KTHXBYE\n", options()).unwrap_err();
+    assert_eq!(errs.len(), 1);
+    assert_eq!(errs[0].message, "expected a new line after the language, found = 1");
+    assert_eq!((errs[0].span.line, errs[0].span.col), (2, 14));
+}
+
+#[test]
+fn html5_pages_have_exactly_one_title() {
+    let opts = || Options { format: HtmlFormat::Html5, ..options() };
+
+    // A title outside HEAD still goes in <head>.
+    let out = compile("#HAI #GIMMEH TITLE Stray #MKAY #MAEK PARAGRAF hi #OIC #KTHXBYE", opts()).unwrap();
+    assert_eq!(out.html.matches("<title>").count(), 1, "{}", out.html);
+    assert!(out.html.contains("<head>\n        <meta charset=\"utf-8\">\n        <title>Stray</title>\n"), "{}", out.html);
+
+    // A second title is dropped with a warning.
+    let src = "#HAI\n#MAEK HEAD #GIMMEH TITLE One #MKAY #OIC\n#MAEK HEAD #GIMMEH TITLE Two #MKAY #OIC\n#KTHXBYE";
+    let out = compile(src, opts()).unwrap();
+    assert_eq!(out.html.matches("<title>").count(), 1, "{}", out.html);
+    assert!(out.html.contains("<title>One</title>"));
+    assert_eq!(out.warnings.len(), 1);
+    assert_eq!(out.warnings[0].message, "the page already has a title; this one is ignored");
+    assert_eq!((out.warnings[0].span.line, out.warnings[0].notes[0].0.line), (3, 2));
+}
+
+#[test]
+fn section_titles_count_as_headings_for_level_checks() {
+    let src = "#HAI #MAEK HEDR 1 Guide #OIC #MAEK SECSHUN Install #MAEK HEDR 3 Notes #OIC #OIC #KTHXBYE";
+    let out = compile(src, options()).unwrap();
+    assert!(out.warnings.is_empty(), "{:?}", out.warnings);
+
+    let src = "#HAI #MAEK SECSHUN Install #OIC #MAEK HEDR 4 Notes #OIC #KTHXBYE";
+    let out = compile(src, options()).unwrap();
+    assert_eq!(out.warnings.len(), 1);
+    assert_eq!(out.warnings[0].message, "heading level 4 skips level 3 (the previous heading is level 2)");
+}
+
+#[test]
+fn any_heading_level_outside_1_to_6_is_a_semantic_error() {
+    for level in ["0", "7", "300", "99999999999"] {
+        let src = format!("#HAI #MAEK HEDR {} Title #OIC #KTHXBYE", level);
+        let errs = compile(&src, options()).unwrap_err();
+        assert_eq!(errs.len(), 1);
+        assert_eq!(errs[0].kind, DiagnosticKind::Semantic, "level {}", level);
+        assert!(errs[0].message.ends_with("is out of range (expected 1 to 6)"), "{}", errs[0].message);
+    }
+}
+
+#[test]
+fn script_urls_are_refused() {
+    for url in ["javascript:alert(1)", "JavaScript:alert(1)", "vbscript:msgbox", "data:text/html,hi"] {
+        let src = format!("#HAI #MAEK PARAGRAF #GIMMEH LINKZ {} #IT IZ click #MKAY #OIC #KTHXBYE", url);
+        let errs = compile(&src, options()).unwrap_err();
+        assert_eq!(errs.len(), 1, "{}", url);
+        assert_eq!(errs[0].kind, DiagnosticKind::Semantic);
+        assert!(errs[0].message.starts_with(&format!("address '{}' uses the", url)), "{}", errs[0].message);
+    }
+    let errs = compile("#HAI #MAEK PARAGRAF #GIMMEH PIKCHUR data:image/png,x #IT IZ dot #MKAY #OIC #KTHXBYE", options()).unwrap_err();
+    assert_eq!(errs[0].kind, DiagnosticKind::Semantic);
+
+    let out = compile("#HAI #MAEK PARAGRAF #GIMMEH LINKZ https://example.com/a:b #MKAY #OIC #KTHXBYE", options()).unwrap();
+    assert!(out.html.contains("<a href=\"https://example.com/a:b\">"), "{}", out.html);
+}
+
+#[test]
+fn expected_keywords_are_spelled_from_the_registry() {
+    let errs = compile("#HAI #MAEK TABL #GIMMEH CELL x #MKAY #OIC #KTHXBYE", options()).unwrap_err();
+    assert_eq!(errs[0].message, "expected MAEK ROW/OBTW/OIC, found GIMMEH");
+    assert_eq!(errs[0].notes[0].1, "this #MAEK TABL was opened here");
+
+    let errs = compile("#HAI #MAEK NUMBRD LIST #GIMMEH BOLD x #MKAY #OIC #KTHXBYE", options()).unwrap_err();
+    assert_eq!(errs[0].message, "expected ITEM, found BOLD");
+    assert_eq!(errs[0].notes[0].1, "this #MAEK NUMBRD LIST was opened here");
+}
+
+#[test]
+fn user_text_is_escaped_for_its_context() {
+    let src = "#HAI\n#OBTW a --> b #TLDR\n#MAEK PARAGRAF 1 < 2 & 3\n\
+               #GIMMEH PIKCHUR cat.png #IT IZ a \"fat\" cat #MKAY\n\
+               #GIMMEH SOUNDZ my song\".mp3 #MKAY\n#GIMMEH VIDZ https://x.test/a b #MKAY\n#OIC\n#KTHXBYE\n";
+    let html = compile(src, options()).unwrap().html;
+    assert!(html.contains("<!-- a - -> b -->"), "{}", html);
+    assert!(html.contains("1 &lt; 2 &amp; 3"), "{}", html);
+    assert!(html.contains("<img src=\"cat.png\" alt=\"a &quot;fat&quot; cat\">"), "{}", html);
+    assert!(html.contains("<source src=\"my%20song%22.mp3\">"), "{}", html);
+    assert!(html.contains("<iframe src=\"https://x.test/a%20b\"/>"), "{}", html);
+}
+
+#[test]
+fn a_broken_element_inside_another_reports_one_error() {
+    let cases = [
+        ("#HAI #MAEK LIST #GIMMEH ITEM a #GIMMEH FOO b #MKAY c #MKAY #OIC #KTHXBYE", "found FOO"),
+        ("#HAI #MAEK TABL #MAEK ROW #GIMMEH CELL a #GIMMEH FOO b #MKAY #MKAY #OIC #OIC #KTHXBYE", "found FOO"),
+        (
+            "#HAI #MAEK PARAGRAF #GIMMEH LINKZ a.html #IT IZ x #GIMMEH LINKZ b.html #MKAY y #MKAY z #OIC #KTHXBYE",
+            "links cannot be nested",
+        ),
+    ];
+    for (src, message) in cases {
+        let errs = compile(src, options()).unwrap_err();
+        let messages: Vec<&str> = errs.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(errs.len(), 1, "{}: {:?}", src, messages);
+        assert!(messages[0].contains(message), "{}: {:?}", src, messages);
+    }
+}